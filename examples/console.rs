@@ -0,0 +1,438 @@
+//! Command line tool to run requests locally, to debug scraping without kite-server.
+//!
+//! Run `cargo run --example console -- help` for usage. It loads `kite.toml` and uses the same
+//! session storage as the agent.
+
+use futures::future::AbortHandle;
+use prettytable::{Cell, Row, Table};
+use serde_json::{json, Value};
+use structopt::StructOpt;
+
+use kite_agent::agent::{self, SharedData};
+use kite_agent::config::{self, CONFIG};
+use kite_agent::error::Result;
+use kite_agent::net::{self, SessionStorage};
+use kite_agent::service::RequestPayload;
+
+#[derive(StructOpt)]
+#[structopt(name = "console", about = "Run agent requests locally.")]
+struct Opt {
+    /// Config file. `KITE_CONFIG` or kite.toml by default.
+    #[structopt(long)]
+    config: Option<String>,
+    /// Print results in JSON instead of tables.
+    #[structopt(long)]
+    json: bool,
+    /// Skip cached responses of public data.
+    #[structopt(long)]
+    no_cache: bool,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt)]
+struct Account {
+    /// Student id.
+    account: String,
+    /// Password of authserver.
+    password: String,
+}
+
+#[derive(StructOpt)]
+struct Term {
+    /// School year, such as 2020 for 2020-2021. All years if not given.
+    #[structopt(long)]
+    year: Option<i32>,
+    /// One of All, FirstTerm, SecondTerm and MidTerm.
+    #[structopt(long, default_value = "All")]
+    semester: String,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Login on authserver and save the session.
+    Login(Account),
+    /// Query timetable.
+    Timetable {
+        #[structopt(flatten)]
+        account: Account,
+        #[structopt(flatten)]
+        term: Term,
+    },
+    /// Query score list.
+    Score {
+        #[structopt(flatten)]
+        account: Account,
+        #[structopt(flatten)]
+        term: Term,
+    },
+    /// Query score detail of a class.
+    ScoreDetail {
+        #[structopt(flatten)]
+        account: Account,
+        #[structopt(flatten)]
+        term: Term,
+        /// Class id in the score list.
+        class_id: String,
+    },
+    /// Query exam arrangement.
+    Exam {
+        #[structopt(flatten)]
+        account: Account,
+        /// Academic year, such as 2020 for 2020-2021.
+        #[structopt(long)]
+        year: u32,
+        /// One of FirstTerm, SecondTerm and MidTerm.
+        #[structopt(long)]
+        semester: String,
+    },
+    /// Query majors of an entrance year.
+    Major {
+        #[structopt(flatten)]
+        account: Account,
+        /// Entrance year. All years if not given.
+        #[structopt(long)]
+        year: Option<i32>,
+    },
+    /// Query campus card expense.
+    Expense {
+        #[structopt(flatten)]
+        account: Account,
+        #[structopt(long)]
+        page: Option<u32>,
+        /// Start date, like 20210101.
+        #[structopt(long)]
+        from: Option<String>,
+        /// End date, like 20211231.
+        #[structopt(long)]
+        to: Option<String>,
+    },
+    /// Library OPAC.
+    Library(LibraryCommand),
+    /// Second class (sc.sit.edu.cn).
+    Sc(ScCommand),
+    /// Sessions in storage.
+    Session(SessionCommand),
+    /// Send a request of any kind with JSON parameter, such as `request ping '"hello"'`.
+    Request {
+        /// Request kind, such as `score` or `ScoreDetail`.
+        kind: String,
+        /// Parameter in JSON.
+        parameter: Option<String>,
+    },
+}
+
+#[derive(StructOpt)]
+enum LibraryCommand {
+    /// Search books.
+    Search {
+        keyword: String,
+        #[structopt(long, default_value = "10")]
+        rows: u16,
+        #[structopt(long, default_value = "1")]
+        page: u32,
+        /// Search way, such as Any, Title, Isbn or Author.
+        #[structopt(long, default_value = "Any")]
+        way: String,
+        /// Sort way, such as MatchScore or PublishDate.
+        #[structopt(long, default_value = "MatchScore")]
+        sort: String,
+        /// Asc or Desc.
+        #[structopt(long, default_value = "Desc")]
+        order: String,
+    },
+    /// Query holding information of books.
+    Holding { book_id: Vec<String> },
+}
+
+#[derive(StructOpt)]
+enum ScCommand {
+    /// List activities.
+    List {
+        #[structopt(long, default_value = "20")]
+        count: u16,
+        #[structopt(long, default_value = "1")]
+        index: u16,
+        /// Category index, 0 for all.
+        #[structopt(long, default_value = "0")]
+        category: i32,
+    },
+    /// Activity detail.
+    Detail { id: i32 },
+    /// My score items.
+    Score(Account),
+    /// My activities.
+    Activity(Account),
+    /// Join an activity.
+    Join {
+        #[structopt(flatten)]
+        account: Account,
+        activity_id: i32,
+        #[structopt(long)]
+        force: bool,
+    },
+}
+
+#[derive(StructOpt)]
+enum SessionCommand {
+    /// List saved sessions.
+    List {
+        #[structopt(long, default_value = "0")]
+        index: u16,
+        #[structopt(long, default_value = "20")]
+        size: u16,
+    },
+    /// Remove all sessions.
+    Clear,
+}
+
+fn school_year(year: Option<i32>) -> Value {
+    match year {
+        Some(year) => json!({ "SomeYear": year }),
+        None => json!("AllYear"),
+    }
+}
+
+/// What a command does: send a request, or work on local storage.
+enum Action {
+    /// Request kind and its JSON parameter.
+    Request(String, Value),
+    Session(SessionCommand),
+}
+
+impl Command {
+    fn into_action(self) -> Result<Action> {
+        let account = |a: &Account| json!({ "account": a.account, "password": a.password });
+        let with = |mut base: Value, extra: Value| {
+            if let (Value::Object(base), Value::Object(extra)) = (&mut base, extra) {
+                base.extend(extra);
+            }
+            base
+        };
+
+        let request = match self {
+            Command::Login(a) => (
+                "PortalAuth",
+                json!({ "account": a.account, "credential": a.password }),
+            ),
+            Command::Timetable { account: a, term } => (
+                "TimeTable",
+                with(
+                    account(&a),
+                    json!({ "school_year": school_year(term.year), "semester": term.semester }),
+                ),
+            ),
+            Command::Score { account: a, term } => (
+                "Score",
+                with(
+                    account(&a),
+                    json!({ "school_year": school_year(term.year), "semester": term.semester }),
+                ),
+            ),
+            Command::ScoreDetail {
+                account: a,
+                term,
+                class_id,
+            } => (
+                "ScoreDetail",
+                with(
+                    account(&a),
+                    json!({
+                        "school_year": school_year(term.year),
+                        "semester": term.semester,
+                        "class_id": class_id,
+                    }),
+                ),
+            ),
+            Command::Exam {
+                account: a,
+                year,
+                semester,
+            } => (
+                "ExamArrange",
+                with(
+                    account(&a),
+                    json!({ "academic_year": year, "semester": semester }),
+                ),
+            ),
+            Command::Major { account: a, year } => (
+                "MajorList",
+                with(account(&a), json!({ "entrance_year": school_year(year) })),
+            ),
+            Command::Expense {
+                account: a,
+                page,
+                from,
+                to,
+            } => (
+                "CardExpense",
+                with(
+                    account(&a),
+                    json!({ "page": page, "start_time": from, "end_time": to }),
+                ),
+            ),
+            Command::Library(LibraryCommand::Search {
+                keyword,
+                rows,
+                page,
+                way,
+                sort,
+                order,
+            }) => (
+                "SearchLibrary",
+                json!({
+                    "keyword": keyword,
+                    "rows": rows,
+                    "page": page,
+                    "search_way": way,
+                    "sort_way": sort,
+                    "sort_order": order,
+                }),
+            ),
+            Command::Library(LibraryCommand::Holding { book_id }) => {
+                ("BookHoldingInfo", json!({ "book_id_list": book_id }))
+            }
+            Command::Sc(ScCommand::List {
+                count,
+                index,
+                category,
+            }) => (
+                "ActivityList",
+                json!({ "count": count, "index": index, "category": category }),
+            ),
+            Command::Sc(ScCommand::Detail { id }) => ("ActivityDetail", json!({ "id": id })),
+            Command::Sc(ScCommand::Score(a)) => ("ScMyScore", account(&a)),
+            Command::Sc(ScCommand::Activity(a)) => ("ScMyActivity", account(&a)),
+            Command::Sc(ScCommand::Join {
+                account: a,
+                activity_id,
+                force,
+            }) => (
+                "ScActivityJoin",
+                with(account(&a), json!({ "activity_id": activity_id, "force": force })),
+            ),
+            Command::Request { kind, parameter } => {
+                let kind = RequestPayload::kind_by_name(&kind)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown request kind: {}", kind))?;
+                let parameter = match parameter {
+                    Some(parameter) => serde_json::from_str(&parameter)?,
+                    None => Value::Null,
+                };
+                (kind, parameter)
+            }
+            Command::Session(command) => return Ok(Action::Session(command)),
+        };
+        Ok(Action::Request(request.0.to_string(), request.1))
+    }
+}
+
+/// Text in a table cell. Long byte arrays such as images are summarized.
+fn text_of(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) if items.len() > 32 && items.iter().all(Value::is_number) => {
+            format!("<{} bytes>", items.len())
+        }
+        _ => value.to_string(),
+    }
+}
+
+fn is_list(value: &Value) -> bool {
+    matches!(value, Value::Array(items) if !items.is_empty() && items.iter().all(Value::is_object))
+}
+
+/// Print a list of objects in a table, one row per object, or an object in a key-value table.
+fn print_table(value: &Value) {
+    match value {
+        Value::Array(items) if is_list(value) => {
+            let mut table = Table::new();
+            let keys: Vec<&String> = items[0].as_object().unwrap().keys().collect();
+
+            table.set_titles(Row::new(keys.iter().map(|k| Cell::new(k)).collect()));
+            for item in items {
+                let cells = keys.iter().map(|k| Cell::new(&text_of(&item[k.as_str()])));
+                table.add_row(Row::new(cells.collect()));
+            }
+            table.printstd();
+        }
+        Value::Object(map) => {
+            let mut table = Table::new();
+            let mut lists = Vec::new();
+
+            for (key, value) in map {
+                if is_list(value) {
+                    lists.push((key, value));
+                } else {
+                    table.add_row(Row::new(vec![Cell::new(key), Cell::new(&text_of(value))]));
+                }
+            }
+            if !table.is_empty() {
+                table.printstd();
+            }
+            for (key, value) in lists {
+                println!("{}:", key);
+                print_table(value);
+            }
+        }
+        _ => println!("{}", text_of(value)),
+    }
+}
+
+fn print(value: &Value, in_json: bool) -> Result<()> {
+    if in_json {
+        println!("{}", serde_json::to_string_pretty(value)?);
+        return Ok(());
+    }
+    // Response payload is encoded as `{ "Kind": content }`, print the content only.
+    match value {
+        Value::Object(map) if map.len() == 1 => print_table(map.values().next().unwrap()),
+        _ => print_table(value),
+    }
+    Ok(())
+}
+
+fn run_session(command: SessionCommand, mut storage: SessionStorage, in_json: bool) -> Result<()> {
+    match command {
+        SessionCommand::List { index, size } => {
+            let sessions: Vec<Value> = storage
+                .list(index, size)?
+                .into_iter()
+                .map(|s| json!({ "account": s.account, "last_update": s.last_update.to_string() }))
+                .collect();
+            print(&Value::Array(sessions), in_json)
+        }
+        SessionCommand::Clear => storage.clear(),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    config::init(opt.config.as_deref())?;
+    let _log_guard = kite_agent::logger::init();
+    let storage = SessionStorage::new()?;
+
+    let (kind, parameter) = match opt.command.into_action()? {
+        Action::Request(kind, parameter) => (kind, parameter),
+        Action::Session(command) => return run_session(command, storage, opt.json),
+    };
+    let payload = RequestPayload::from_json(&kind, parameter)?;
+    let data = SharedData {
+        node: CONFIG.agent.name.clone(),
+        client: net::build_http_client()?,
+        session_store: storage,
+        stream: None,
+        batch: None,
+        no_cache: opt.no_cache,
+    };
+
+    let (_handle, registration) = AbortHandle::new_pair();
+    match agent::process(payload, data, None, registration).await {
+        Ok(payload) => print(&serde_json::to_value(&payload)?, opt.json),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! Mock kite-server running a scripted session against agents, to try the protocol locally.
+//!
+//! Run `cargo run --features mock --example mock_server -- 127.0.0.1:8443 change-me`, then start
+//! the agent with `server.addr` set to the address and `server.codec = "json"`.
+
+use serde_json::{json, Value};
+
+use kite_agent::agent::codec::Codec;
+use kite_agent::error::Result;
+use kite_agent::mock::{MockConnection, MockServer};
+
+fn print(title: &str, value: &Value) {
+    println!("{}: {}", title, value);
+}
+
+async fn run_session(mut connection: MockConnection) -> Result<()> {
+    let hello = &connection.hello;
+    println!(
+        "Agent {} v{} connected, {} request kinds.",
+        hello.node,
+        hello.agent_version,
+        hello.capabilities.len()
+    );
+
+    connection.request(1, json!({ "Ping": "hello" })).await?;
+    connection.request(2, json!({ "AgentInfo": null })).await?;
+    for _ in 0..2 {
+        let (tag, payload) = connection.recv_response().await?;
+        print(&format!("Response {}", tag), &payload);
+    }
+
+    // Cancelling an unknown tag is ignored by agent.
+    connection.cancel(99).await?;
+    connection.send(&json!("Heartbeat")).await?;
+
+    let mut client = connection.into_client();
+    let payloads = (0..8)
+        .map(|i| json!({ "Ping": format!("concurrent {}", i) }))
+        .collect();
+    for payload in client.call_all(payloads).await? {
+        print("Concurrent response", &payload);
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let addr = args.next().unwrap_or_else(|| "127.0.0.1:8443".to_string());
+    let secret = args.next().unwrap_or_else(|| "change-me".to_string());
+
+    let server = MockServer::bind(&addr, &secret, Codec::Json).await?;
+    println!("Mock server listening on {}", server.local_addr()?);
+
+    loop {
+        match server.accept().await {
+            Ok(connection) => {
+                tokio::spawn(async move {
+                    if let Err(e) = run_session(connection).await {
+                        eprintln!("Session failed: {}", e);
+                    }
+                });
+            }
+            Err(e) => eprintln!("Handshake failed: {}", e),
+        }
+    }
+}
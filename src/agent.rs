@@ -1,20 +1,68 @@
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
-use async_bincode::AsyncBincodeStream;
+use futures::future::{AbortRegistration, Abortable};
 use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
 use tokio_tower::multiplex;
 use tokio_tower::multiplex::Server;
 use tower::Service;
+use tracing::{debug, info, Instrument};
 
+use crate::config::CONFIG;
 use crate::error::{AgentError, Result};
-use crate::service::{RequestPayload, ResponsePayload, ResponseResult};
+use crate::metrics;
+use crate::service::{ActionError, BatchSessions, RequestPayload, ResponsePayload, ResponseResult};
 use crate::SessionStorage;
 
+use channel::{AgentFrame, Channel};
+use codec::Codec;
+use inflight::InFlight;
+use limit::LIMITER;
+pub use stream::ResponseStream;
+pub use transport::BoxedTransport;
+
+pub(crate) mod auth;
+pub mod backoff;
+mod channel;
+pub mod codec;
+pub mod event;
+pub(crate) mod frame;
+pub mod handshake;
+mod inflight;
+pub mod limit;
+pub mod live;
+pub mod reload;
+pub mod status;
+mod stream;
+mod transport;
+pub mod worker;
+
+/// Max time to wait for in-flight requests before closing a connection on purpose.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+const DRAIN_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+/// Time to keep the connection after in-flight requests finish, so that their responses are sent.
+const FLUSH_TIME: Duration = Duration::from_millis(500);
+
+/// Request from server. Fields other than `payload` may be omitted by older servers, with the JSON
+/// and MessagePack codecs.
 #[derive(Debug, Deserialize)]
 struct RequestFrame {
     payload: RequestPayload,
+    /// Deadline in milliseconds, counted from the moment agent receives the frame. The request is
+    /// given up with a timeout error when it expires.
+    #[serde(default)]
+    deadline: Option<u64>,
+    /// Ask for a streamed response if the request supports it.
+    #[serde(default)]
+    stream: bool,
+    /// Skip the cached response and fetch again. The new response is still cached.
+    #[serde(default)]
+    no_cache: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -35,11 +83,27 @@ pub struct SharedData {
     pub node: String,
     pub client: reqwest::Client,
     pub session_store: SessionStorage,
+    /// Set if the current request asks for a streamed response.
+    pub stream: Option<ResponseStream>,
+    /// Set if the current request is a part of a batch.
+    pub batch: Option<BatchSessions>,
+    /// Skip cached responses, see `RequestFrame.no_cache`.
+    pub no_cache: bool,
 }
 
+impl SharedData {
+    /// Take the node name and HTTP client from reloaded config, if any.
+    pub fn refresh(&mut self) {
+        if let Some(live) = live::get() {
+            self.node = live.node;
+            self.client = live.client;
+        }
+    }
+}
+
+/// Tag store of the multiplex client, used by the mock server.
 #[derive(Debug, Default)]
-// only pub because we use it to figure out the error type for ViewError
-struct Tagger(slab::Slab<()>);
+pub(crate) struct Tagger(slab::Slab<()>);
 
 impl<Request: core::fmt::Debug, Response: core::fmt::Debug>
     multiplex::TagStore<Tagged<Request>, Tagged<Response>> for Tagger
@@ -57,7 +121,7 @@ impl<Request: core::fmt::Debug, Response: core::fmt::Debug>
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
-struct Tagged<T>
+pub(crate) struct Tagged<T>
 where
     T: core::fmt::Debug,
 {
@@ -74,6 +138,58 @@ impl<T: core::fmt::Debug> From<T> for Tagged<T> {
 #[derive(Debug, Clone)]
 struct KiteService {
     shared_data: SharedData,
+    in_flight: InFlight,
+    /// Frames of streamed responses.
+    outgoing: mpsc::Sender<AgentFrame>,
+    /// Set when the connection is to be closed. New requests are rejected as busy meanwhile.
+    draining: Arc<AtomicBool>,
+}
+
+/// Dispatch the request under concurrency limits, and give up when it's cancelled or the deadline
+/// expires. Time waiting for a free slot is counted in the deadline.
+pub async fn process(
+    payload: RequestPayload,
+    data: SharedData,
+    deadline: Option<Duration>,
+    registration: AbortRegistration,
+) -> ResponseResult {
+    let kind = payload.kind();
+    let timer = metrics::REQUEST_DURATION.with_label_values(&[kind]).start_timer();
+
+    metrics::REQUESTS.with_label_values(&[kind]).inc();
+    let result = dispatch_abortable(payload, data, deadline, registration).await;
+    timer.observe_duration();
+
+    if let Err(e) = &result {
+        metrics::ERRORS.with_label_values(&[&e.code.to_string()]).inc();
+        info!(code = e.code, "Request failed: {}", e.msg);
+    }
+    result
+}
+
+async fn dispatch_abortable(
+    payload: RequestPayload,
+    data: SharedData,
+    deadline: Option<Duration>,
+    registration: AbortRegistration,
+) -> ResponseResult {
+    let task = Abortable::new(dispatch_limited(payload, data), registration);
+    let result = match deadline {
+        Some(deadline) => tokio::time::timeout(deadline, task)
+            .await
+            .map_err(|_| ActionError::Timeout)?,
+        None => task.await,
+    };
+    result.map_err(|_| ActionError::Cancelled)?
+}
+
+/// Dispatch the request after taking slots of its campus system and a global one. Sub-requests of
+/// a batch run under the global slot of the batch.
+pub(crate) async fn dispatch_limited(payload: RequestPayload, data: SharedData) -> ResponseResult {
+    let global = data.batch.is_none();
+    let _permit = LIMITER.acquire(payload.target(), global).await?;
+
+    payload.dispatch(data).await
 }
 
 impl Service<Tagged<RequestFrame>> for KiteService {
@@ -81,21 +197,52 @@ impl Service<Tagged<RequestFrame>> for KiteService {
     type Error = anyhow::Error;
     type Future = Pin<Box<dyn Future<Output = std::result::Result<Self::Response, Self::Error>>>>;
 
+    /// Always ready, so that link frames keep being read. Requests are limited in `call` instead,
+    /// see `limit` module.
     fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<std::result::Result<(), Self::Error>> {
         Poll::Ready(Ok(()))
     }
 
     fn call(&mut self, req: Tagged<RequestFrame>) -> Self::Future {
+        if self.draining.load(Ordering::SeqCst) {
+            let response_frame = ResponseFrame {
+                payload: Err(ActionError::Busy.into()),
+            };
+            let mut response = Tagged::<ResponseFrame>::from(response_frame);
+
+            response.tag = req.tag;
+            return Box::pin(async move { Ok(response) });
+        }
         // Note: Maybe improve performance
-        let data = self.shared_data.clone();
+        let mut data = self.shared_data.clone();
+        let (registration, guard) = self.in_flight.register(req.tag);
+
+        data.refresh();
+
+        if req.v.stream {
+            data.stream = Some(ResponseStream::new(req.tag, self.outgoing.clone()));
+        }
+        data.no_cache = req.v.no_cache;
+
+        let span = tracing::info_span!(
+            "request",
+            tag = req.tag,
+            kind = req.v.payload.kind(),
+            account = tracing::field::Empty,
+        );
+        if let Some(account) = req.v.payload.account() {
+            span.record("account", account);
+        }
 
         let f = async move {
+            let _guard = guard;
             let tag = req.tag;
-            println!("Received frame: {:?}, tag = {}", &req.v, tag);
+            debug!(deadline = ?req.v.deadline, stream = req.v.stream, "Request received.");
 
             let request_frame = req.v;
+            let deadline = request_frame.deadline.map(Duration::from_millis);
             let response_frame = ResponseFrame {
-                payload: request_frame.payload.dispatch(data).await,
+                payload: process(request_frame.payload, data, deadline, registration).await,
             };
             let mut response = Tagged::<ResponseFrame>::from(response_frame);
 
@@ -103,26 +250,94 @@ impl Service<Tagged<RequestFrame>> for KiteService {
             Ok(response)
         };
 
-        Box::pin(f)
+        Box::pin(f.instrument(span))
     }
 }
 
-pub async fn run(server_address: String, shared_data: SharedData) -> Result<()> {
-    println!("Connecting to server: {}", server_address);
+/// A connection to server after handshake.
+pub struct Connection {
+    transport: BoxedTransport,
+    /// Codec of frames, negotiated in handshake.
+    codec: Codec,
+}
+
+/// Connect to server and finish the handshake. The returned connection is ready to serve.
+pub async fn connect(server_address: &str, node: &str) -> Result<Connection> {
+    let secret = CONFIG.server.secret.as_deref().ok_or(AgentError::NoSecret)?;
+
+    info!("Connecting to server: {}", server_address);
     // Create a socket and connect to server.
-    let socket = tokio::net::TcpStream::connect(server_address)
-        .await
-        .map_err(|_| AgentError::ConnectionFailure)?;
+    let mut socket = transport::connect(server_address).await?;
 
-    println!("Connected.");
+    info!("Connected.");
+    let ack = handshake::perform(&mut socket, node, secret).await?;
+    let codec = ack.codec.unwrap_or_default();
+    info!(
+        "Handshake accepted, server protocol version {}, codec {:?}.",
+        ack.protocol_version, codec
+    );
+    Ok(Connection {
+        transport: socket,
+        codec,
+    })
+}
 
-    Server::new(
-        AsyncBincodeStream::from(socket).for_async(),
-        KiteService { shared_data },
-    )
-    .await
-    .map_err(|e| AgentError::Service(e.to_string()))?;
+/// Serve requests from server on the connected stream until disconnected.
+pub async fn serve(connection: Connection, shared_data: SharedData) -> Result<()> {
+    serve_until(connection, shared_data, futures::future::pending()).await
+}
+
+/// Serve requests like `serve`, and close the connection after `stop` completes. In-flight
+/// requests are given `DRAIN_TIMEOUT` to finish before closing, while new ones are rejected as
+/// busy, so that server sends them to other connections.
+pub async fn serve_until<F>(connection: Connection, shared_data: SharedData, stop: F) -> Result<()>
+where
+    F: Future<Output = ()>,
+{
+    let in_flight = InFlight::default();
+    let draining = Arc::new(AtomicBool::new(false));
+    let (outgoing_tx, outgoing_rx) = mpsc::channel(stream::QUEUE_SIZE);
+    let channel = Channel::new(
+        connection.transport,
+        connection.codec,
+        Duration::from_secs(CONFIG.server.heartbeat_interval),
+        Duration::from_secs(CONFIG.server.heartbeat_timeout),
+        in_flight.clone(),
+        outgoing_rx,
+    );
+    let service = KiteService {
+        shared_data,
+        in_flight: in_flight.clone(),
+        outgoing: outgoing_tx,
+        draining: draining.clone(),
+    };
+    let server = Server::new(channel, service);
+
+    tokio::pin!(server, stop);
+    let mut result = tokio::select! {
+        result = &mut server => Some(result),
+        _ = &mut stop => None,
+    };
+    if result.is_none() {
+        info!(in_flight = in_flight.len(), "Draining connection.");
+        draining.store(true, Ordering::SeqCst);
+
+        // Keep the server running, so that responses are sent.
+        let drained = async {
+            while in_flight.len() > 0 {
+                tokio::time::sleep(DRAIN_CHECK_INTERVAL).await;
+            }
+            tokio::time::sleep(FLUSH_TIME).await;
+        };
+        tokio::select! {
+            closed = &mut server => result = Some(closed),
+            _ = tokio::time::timeout(DRAIN_TIMEOUT, drained) => {}
+        }
+    }
+    if let Some(result) = result {
+        result.map_err(|e| AgentError::Service(e.to_string()))?;
+    }
 
-    println!("Disconnected.");
+    info!("Disconnected.");
     Ok(())
 }
@@ -0,0 +1,85 @@
+//! Shared-secret challenge-response, so that both agent and server prove they know the secret
+//! without sending it.
+//!
+//! Each side generates a random nonce. The server proves itself with
+//! `HMAC-SHA256(secret, "kite-server" || agent_nonce || server_nonce)`, and the agent answers with
+//! `HMAC-SHA256(secret, "kite-agent" || server_nonce || agent_nonce)`.
+
+use rand::RngCore;
+use ring::hmac;
+
+const SERVER_LABEL: &[u8] = b"kite-server";
+const AGENT_LABEL: &[u8] = b"kite-agent";
+/// Length of nonce in bytes.
+pub const NONCE_SIZE: usize = 32;
+
+/// Generate a random nonce.
+pub fn generate_nonce() -> Vec<u8> {
+    let mut nonce = vec![0u8; NONCE_SIZE];
+
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+    nonce
+}
+
+fn sign(secret: &str, label: &[u8], first: &[u8], second: &[u8]) -> Vec<u8> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let mut context = hmac::Context::with_key(&key);
+
+    context.update(label);
+    context.update(first);
+    context.update(second);
+    context.sign().as_ref().to_vec()
+}
+
+fn verify(secret: &str, label: &[u8], first: &[u8], second: &[u8], proof: &[u8]) -> bool {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let mut message = Vec::with_capacity(label.len() + first.len() + second.len());
+
+    message.extend_from_slice(label);
+    message.extend_from_slice(first);
+    message.extend_from_slice(second);
+    hmac::verify(&key, &message, proof).is_ok()
+}
+
+/// Check the proof sent by server.
+pub fn verify_server_proof(secret: &str, agent_nonce: &[u8], server_nonce: &[u8], proof: &[u8]) -> bool {
+    verify(secret, SERVER_LABEL, agent_nonce, server_nonce, proof)
+}
+
+/// Generate the proof of agent.
+pub fn agent_proof(secret: &str, agent_nonce: &[u8], server_nonce: &[u8]) -> Vec<u8> {
+    sign(secret, AGENT_LABEL, server_nonce, agent_nonce)
+}
+
+/// Generate the proof of server. Used by the mock server.
+pub fn server_proof(secret: &str, agent_nonce: &[u8], server_nonce: &[u8]) -> Vec<u8> {
+    sign(secret, SERVER_LABEL, agent_nonce, server_nonce)
+}
+
+/// Check the proof sent by agent. Used by the mock server.
+pub fn verify_agent_proof(secret: &str, agent_nonce: &[u8], server_nonce: &[u8], proof: &[u8]) -> bool {
+    verify(secret, AGENT_LABEL, server_nonce, agent_nonce, proof)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_server_proof() {
+        let agent_nonce = generate_nonce();
+        let server_nonce = generate_nonce();
+        let proof = sign("secret", SERVER_LABEL, &agent_nonce, &server_nonce);
+
+        assert!(verify_server_proof("secret", &agent_nonce, &server_nonce, &proof));
+        assert!(!verify_server_proof("wrong", &agent_nonce, &server_nonce, &proof));
+        // An agent proof must not be accepted as a server proof.
+        let reflected = agent_proof("secret", &agent_nonce, &server_nonce);
+        assert!(!verify_server_proof(
+            "secret",
+            &agent_nonce,
+            &server_nonce,
+            &reflected
+        ));
+    }
+}
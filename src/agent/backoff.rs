@@ -0,0 +1,68 @@
+//! Exponential backoff with jitter, so that agents do not retry at the same moment.
+
+use std::time::Duration;
+
+use rand::Rng;
+
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    /// Delay of the first retry.
+    min: Duration,
+    /// Upper bound of delay.
+    max: Duration,
+    /// Consecutive failure count.
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self { min, max, attempt: 0 }
+    }
+
+    /// Continue from a failure count, such as one saved before restart.
+    pub fn resume(min: Duration, max: Duration, attempt: u32) -> Self {
+        Self { min, max, attempt }
+    }
+
+    /// Consecutive failure count since last reset.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Calculate delay of the next retry, and increase the failure count.
+    /// The delay doubles on each failure up to `max`, and is randomized in the upper half of it.
+    pub fn next_delay(&mut self) -> Duration {
+        let factor = 2u32.saturating_pow(self.attempt);
+        let delay = self.min.checked_mul(factor).unwrap_or(self.max).min(self.max);
+
+        self.attempt = self.attempt.saturating_add(1);
+        let half = delay / 2;
+        half + rand::thread_rng().gen_range(Duration::ZERO..=half)
+    }
+
+    /// Reset after a successful attempt.
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Backoff;
+    use std::time::Duration;
+
+    #[test]
+    fn test_backoff_bounds() {
+        let mut backoff = Backoff::new(Duration::from_secs(2), Duration::from_secs(60));
+
+        for attempt in 0..20 {
+            let expected =
+                Duration::from_secs(2 * 2u64.pow(attempt.min(5))).min(Duration::from_secs(60));
+            let delay = backoff.next_delay();
+
+            assert!(delay >= expected / 2 && delay <= expected);
+        }
+        backoff.reset();
+        assert!(backoff.next_delay() <= Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,298 @@
+//! Frames on the wire after handshake, and the transport adapter used by multiplex server.
+//!
+//! Requests and responses are wrapped in `ServerFrame` and `AgentFrame`, so that link level
+//! frames such as heartbeat can be carried on the same connection. `Channel` handles these frames
+//! by itself, and only passes tagged requests and responses through. Frames are encoded with the
+//! codec negotiated in handshake.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::{Sink, Stream};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc;
+use tokio::time::{Instant, Interval, Sleep};
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+use crate::error::{AgentError, Result};
+use crate::service::ResponseChunk;
+
+use super::codec::Codec;
+use super::event::{AgentEvent, Unacked};
+use super::inflight::InFlight;
+use super::{RequestFrame, ResponseFrame, Tagged};
+
+/// Frame sent by server.
+#[derive(Debug, Deserialize)]
+pub(super) enum ServerFrame {
+    /// A tagged request.
+    Request(Tagged<RequestFrame>),
+    /// Server is alive.
+    Heartbeat,
+    /// Drop the request with the tag, server is no longer waiting for it.
+    Cancel(u32),
+    /// Server received the event with the id.
+    EventAck(u32),
+}
+
+/// Frame sent by agent.
+#[derive(Debug, Serialize)]
+pub(super) enum AgentFrame {
+    /// Response to the request with the same tag. For streamed request, it marks the end.
+    Response(Tagged<ResponseFrame>),
+    /// Part of a streamed response.
+    Chunk(Tagged<ResponseChunk>),
+    /// Agent is alive.
+    Heartbeat,
+    /// Event emitted by agent, to be acknowledged by server with the same id.
+    Event { id: u32, event: AgentEvent },
+}
+
+/// Max size of a frame. Large results should be streamed instead.
+const MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+pub(super) struct Channel<S> {
+    /// Length-prefixed frames.
+    inner: Framed<S, LengthDelimitedCodec>,
+    codec: Codec,
+    /// Timer to send heartbeat.
+    heartbeat: Interval,
+    /// A heartbeat is to be sent but the sink was not ready.
+    heartbeat_due: bool,
+    /// Link is considered dead if nothing received before it.
+    deadline: Pin<Box<Sleep>>,
+    timeout: Duration,
+    /// Requests being processed, to handle cancel frame.
+    in_flight: InFlight,
+    /// Frames from streamed responses, to be sent before any later response.
+    outgoing: mpsc::Receiver<AgentFrame>,
+    /// A frame taken from `outgoing` but the sink was not ready.
+    pending: Option<AgentFrame>,
+    /// Events sent on the channel.
+    events: Unacked,
+}
+
+impl<S> Channel<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    pub fn new(
+        stream: S,
+        codec: Codec,
+        interval: Duration,
+        timeout: Duration,
+        in_flight: InFlight,
+        outgoing: mpsc::Receiver<AgentFrame>,
+    ) -> Self {
+        let mut heartbeat = tokio::time::interval(interval);
+        heartbeat.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        Self {
+            inner: LengthDelimitedCodec::builder()
+                .max_frame_length(MAX_FRAME_SIZE)
+                .new_framed(stream),
+            codec,
+            heartbeat,
+            heartbeat_due: false,
+            deadline: Box::pin(tokio::time::sleep(timeout)),
+            timeout,
+            in_flight,
+            outgoing,
+            pending: None,
+            events: Unacked::new(),
+        }
+    }
+
+    /// Encode the frame and put it to sink. `poll_ready` must have returned ready.
+    fn start_send_frame(&mut self, frame: &AgentFrame) -> Result<()> {
+        let content = self.codec.encode(frame)?;
+
+        Pin::new(&mut self.inner).start_send(Bytes::from(content))?;
+        Ok(())
+    }
+
+    /// Move queued frames and events to sink. Ready when both queues are empty.
+    fn poll_outgoing(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        loop {
+            if let Some(frame) = self.pending.take() {
+                match Sink::<Bytes>::poll_ready(Pin::new(&mut self.inner), cx) {
+                    Poll::Ready(r) => {
+                        r?;
+                        self.start_send_frame(&frame)?;
+                    }
+                    Poll::Pending => {
+                        self.pending = Some(frame);
+                        return Poll::Pending;
+                    }
+                }
+            }
+            if let Poll::Ready(Some(frame)) = self.outgoing.poll_recv(cx) {
+                self.pending = Some(frame);
+            } else if let Poll::Ready((id, event)) = self.events.poll_next(cx) {
+                self.pending = Some(AgentFrame::Event { id, event });
+            } else {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+
+    /// Send heartbeat if it's time to.
+    fn poll_heartbeat(&mut self, cx: &mut Context<'_>) -> Result<()> {
+        if self.heartbeat.poll_tick(cx).is_ready() {
+            self.heartbeat_due = true;
+        }
+        if self.heartbeat_due {
+            if let Poll::Ready(r) = Sink::<Bytes>::poll_ready(Pin::new(&mut self.inner), cx) {
+                r?;
+                self.start_send_frame(&AgentFrame::Heartbeat)?;
+                self.heartbeat_due = false;
+            }
+        }
+        Ok(())
+    }
+
+    fn refresh_deadline(&mut self) {
+        let next = Instant::now() + self.timeout;
+        self.deadline.as_mut().reset(next);
+    }
+}
+
+impl<S> Stream for Channel<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Item = Result<Tagged<RequestFrame>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.as_mut().get_mut();
+
+        if let Err(e) = this.poll_heartbeat(cx) {
+            return Poll::Ready(Some(Err(e)));
+        }
+        // Streamed chunks and heartbeat are produced while no response is sent, so forward and
+        // flush them here too. Multiplex server may not flush until the next request arrives.
+        if let Poll::Ready(Err(e)) = this.poll_outgoing(cx) {
+            return Poll::Ready(Some(Err(e)));
+        }
+        if let Poll::Ready(Err(e)) = Sink::<Bytes>::poll_flush(Pin::new(&mut this.inner), cx) {
+            return Poll::Ready(Some(Err(e.into())));
+        }
+        loop {
+            match Pin::new(&mut this.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(content))) => {
+                    this.refresh_deadline();
+                    let frame = match this.codec.decode(&content) {
+                        Ok(frame) => frame,
+                        Err(e) => return Poll::Ready(Some(Err(e))),
+                    };
+                    match frame {
+                        ServerFrame::Request(request) => return Poll::Ready(Some(Ok(request))),
+                        ServerFrame::Heartbeat => continue,
+                        ServerFrame::Cancel(tag) => {
+                            this.in_flight.cancel(tag);
+                        }
+                        ServerFrame::EventAck(id) => {
+                            // More events may be sent now.
+                            if this.events.ack(id) {
+                                cx.waker().wake_by_ref();
+                            }
+                        }
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e.into()))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => break,
+            }
+        }
+        if this.deadline.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Some(Err(AgentError::HeartbeatTimeout.into())));
+        }
+        Poll::Pending
+    }
+}
+
+impl<S> Sink<Tagged<ResponseFrame>> for Channel<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Error = anyhow::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        // Chunks of a streamed request are queued before its final response.
+        futures::ready!(self.poll_outgoing(cx))?;
+        Sink::<Bytes>::poll_ready(Pin::new(&mut self.inner), cx).map_err(Into::into)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Tagged<ResponseFrame>) -> Result<()> {
+        self.start_send_frame(&AgentFrame::Response(item))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Sink::<Bytes>::poll_flush(Pin::new(&mut self.inner), cx).map_err(Into::into)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Sink::<Bytes>::poll_close(Pin::new(&mut self.inner), cx).map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use bincode::Options;
+    use futures::StreamExt;
+    use tokio::io::AsyncReadExt;
+
+    use super::{AgentFrame, Channel, Codec, InFlight, RequestFrame};
+    use crate::error::AgentError;
+
+    #[test]
+    fn test_request_frame_defaults() {
+        // Sent by servers not knowing deadline, stream and no_cache.
+        let frame = serde_json::json!({ "payload": { "Ping": "a" } });
+
+        for codec in &[Codec::Json, Codec::MessagePack] {
+            let content = codec.encode(&frame).unwrap();
+            let decoded: RequestFrame = codec.decode(&content).unwrap();
+
+            assert_eq!(decoded.deadline, None);
+            assert!(!decoded.stream && !decoded.no_cache);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_timeout() {
+        let (local, mut remote) = tokio::io::duplex(1024);
+        let (_sender, receiver) = tokio::sync::mpsc::channel(1);
+        let mut channel = Channel::new(
+            local,
+            Codec::Bincode,
+            Duration::from_millis(20),
+            Duration::from_millis(100),
+            InFlight::default(),
+            receiver,
+        );
+
+        // Nothing is sent by remote, so the link is considered dead.
+        let result = channel.next().await.unwrap();
+        let e = result.unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<AgentError>(),
+            Some(AgentError::HeartbeatTimeout)
+        ));
+
+        // Heartbeat is sent before that.
+        let size = remote.read_u32().await.unwrap();
+        let mut content = vec![0u8; size as usize];
+        remote.read_exact(&mut content).await.unwrap();
+        assert_eq!(
+            content,
+            bincode::options().serialize(&AgentFrame::Heartbeat).unwrap()
+        );
+    }
+}
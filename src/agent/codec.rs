@@ -0,0 +1,98 @@
+//! Encoding of frames after handshake. All codecs share the same framing: a four-byte
+//! network-endian length followed by the encoded frame.
+//!
+//! Agent lists the codecs it supports in `Hello`, the configured one first, and server picks one
+//! in `HelloAck`. Bincode is used if server does not pick any.
+
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::config::CONFIG;
+use crate::error::{AgentError, Result};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    /// Compact, but both sides must have exactly the same types.
+    #[default]
+    Bincode,
+    /// MessagePack with named struct fields.
+    #[serde(alias = "msgpack")]
+    MessagePack,
+    /// Readable on the wire.
+    Json,
+}
+
+impl Codec {
+    pub const ALL: [Codec; 3] = [Codec::Bincode, Codec::MessagePack, Codec::Json];
+
+    pub fn encode<T: Serialize>(self, item: &T) -> Result<Vec<u8>> {
+        let content = match self {
+            Codec::Bincode => bincode::options().serialize(item)?,
+            Codec::MessagePack => rmp_serde::to_vec_named(item)?,
+            Codec::Json => serde_json::to_vec(item)?,
+        };
+        Ok(content)
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, content: &[u8]) -> Result<T> {
+        let item = match self {
+            Codec::Bincode => bincode::options()
+                .deserialize(content)
+                .map_err(|e| AgentError::Codec(e.to_string()))?,
+            Codec::MessagePack => {
+                rmp_serde::from_slice(content).map_err(|e| AgentError::Codec(e.to_string()))?
+            }
+            Codec::Json => {
+                serde_json::from_slice(content).map_err(|e| AgentError::Codec(e.to_string()))?
+            }
+        };
+        Ok(item)
+    }
+}
+
+/// Supported codecs in preference order, the configured one first.
+pub fn preferred() -> Vec<Codec> {
+    let first = CONFIG.server.codec;
+
+    std::iter::once(first)
+        .chain(Codec::ALL.iter().copied().filter(|c| *c != first))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use serde::{Deserialize, Serialize};
+
+    use super::Codec;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Frame {
+        Heartbeat,
+        Data {
+            tag: u32,
+            content: Vec<u8>,
+            text: Option<String>,
+        },
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let frames = vec![
+            Frame::Heartbeat,
+            Frame::Data {
+                tag: 3,
+                content: vec![0, 1, 255],
+                text: Some("上应小风筝".to_string()),
+            },
+        ];
+        for codec in Codec::ALL.iter() {
+            let content = codec.encode(&frames).unwrap();
+            let decoded: Vec<Frame> = codec.decode(&content).unwrap();
+
+            assert_eq!(decoded, frames);
+            assert!(codec.decode::<Vec<Frame>>(&content[..content.len() - 1]).is_err());
+        }
+    }
+}
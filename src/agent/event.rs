@@ -0,0 +1,171 @@
+//! Events sent by agent unprompted, such as session expiry.
+//!
+//! Events are queued in the process and taken by any connected channel. An event sent on a channel
+//! is kept there until server acknowledges it, and is put back to the queue if the channel is
+//! closed before that, so that it is delivered at least once.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+
+use serde::Serialize;
+
+lazy_static! {
+    static ref EVENTS: EventQueue = EventQueue::default();
+}
+
+/// Max events queued. The oldest one is dropped when it's full.
+const QUEUE_SIZE: usize = 1024;
+/// Max events sent on a channel and waiting for acknowledgement.
+const MAX_UNACKED: usize = 64;
+
+#[derive(Debug, Clone, Serialize)]
+pub enum AgentEvent {
+    /// Stored session of the account expired and could not be renewed with its password.
+    SessionExpired { account: String },
+    /// A job finished, with its result in JSON encoded `ResponseResult`.
+    JobFinished { id: u64, result: String },
+}
+
+#[derive(Default)]
+struct Inner {
+    next_id: u32,
+    events: VecDeque<(u32, AgentEvent)>,
+    /// Channels waiting for new events.
+    wakers: Vec<Waker>,
+}
+
+#[derive(Default)]
+struct EventQueue(Mutex<Inner>);
+
+impl EventQueue {
+    fn push(&self, event: AgentEvent) {
+        let mut inner = self.0.lock().unwrap();
+
+        if inner.events.len() >= QUEUE_SIZE {
+            tracing::warn!("Event queue is full, drop the oldest one.");
+            inner.events.pop_front();
+        }
+        let id = inner.next_id;
+        inner.next_id = id.wrapping_add(1);
+        inner.events.push_back((id, event));
+        inner.wakers.drain(..).for_each(Waker::wake);
+    }
+
+    /// Take the next event, or wake the task when one is emitted.
+    fn poll_pop(&self, cx: &mut Context<'_>) -> Poll<(u32, AgentEvent)> {
+        let mut inner = self.0.lock().unwrap();
+
+        if let Some(event) = inner.events.pop_front() {
+            return Poll::Ready(event);
+        }
+        if !inner.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            inner.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+
+    /// Put events not acknowledged back to the front, in their original order.
+    fn requeue(&self, events: Vec<(u32, AgentEvent)>) {
+        let mut inner = self.0.lock().unwrap();
+
+        for event in events.into_iter().rev() {
+            inner.events.push_front(event);
+        }
+        inner.wakers.drain(..).for_each(Waker::wake);
+    }
+}
+
+/// Emit an event to server. It's sent on any connection.
+pub fn emit(event: AgentEvent) {
+    EVENTS.push(event);
+}
+
+/// Events sent on a channel but not acknowledged. They are put back to the queue when dropped.
+pub(super) struct Unacked {
+    queue: &'static EventQueue,
+    events: BTreeMap<u32, AgentEvent>,
+}
+
+impl Unacked {
+    pub fn new() -> Self {
+        Self::with_queue(&EVENTS)
+    }
+
+    fn with_queue(queue: &'static EventQueue) -> Self {
+        Self {
+            queue,
+            events: BTreeMap::new(),
+        }
+    }
+
+    /// Take the next event to send, unless too many are waiting for acknowledgement.
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<(u32, AgentEvent)> {
+        if self.events.len() >= MAX_UNACKED {
+            return Poll::Pending;
+        }
+        let (id, event) = futures::ready!(self.queue.poll_pop(cx));
+        self.events.insert(id, event.clone());
+        Poll::Ready((id, event))
+    }
+
+    /// Server received the event. Return false if it's unknown.
+    pub fn ack(&mut self, id: u32) -> bool {
+        self.events.remove(&id).is_some()
+    }
+}
+
+impl Drop for Unacked {
+    fn drop(&mut self) {
+        let events = std::mem::take(&mut self.events);
+        if !events.is_empty() {
+            self.queue.requeue(events.into_iter().collect());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::{Context, Poll};
+
+    use futures::task::noop_waker;
+
+    use super::{AgentEvent, EventQueue, Unacked};
+
+    fn account_of(event: AgentEvent) -> String {
+        match event {
+            AgentEvent::SessionExpired { account } => account,
+            _ => panic!("Unexpected event"),
+        }
+    }
+
+    #[test]
+    fn test_requeue_unacked() {
+        let queue: &'static EventQueue = Box::leak(Box::new(EventQueue::default()));
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        for account in &["a", "b"] {
+            queue.push(AgentEvent::SessionExpired {
+                account: account.to_string(),
+            });
+        }
+        let mut unacked = Unacked::with_queue(queue);
+        let (first, _) = match unacked.poll_next(&mut cx) {
+            Poll::Ready(event) => event,
+            Poll::Pending => panic!("Event expected"),
+        };
+        assert!(unacked.poll_next(&mut cx).is_ready());
+        assert!(unacked.poll_next(&mut cx).is_pending());
+        assert!(unacked.ack(first));
+        assert!(!unacked.ack(first));
+
+        // The second event is not acknowledged, so it's sent again on another channel.
+        drop(unacked);
+        let mut unacked = Unacked::with_queue(queue);
+        match unacked.poll_next(&mut cx) {
+            Poll::Ready((_, event)) => assert_eq!(account_of(event), "b"),
+            Poll::Pending => panic!("Event expected"),
+        }
+    }
+}
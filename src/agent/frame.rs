@@ -0,0 +1,48 @@
+//! Single frame read and write helpers used before the multiplexed stream starts.
+//!
+//! Frames are laid out the same way as the multiplexed stream: a four-byte network-endian length
+//! followed by the payload. Handshake frames are always encoded in bincode.
+
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::error::Result;
+
+/// Control frames are small. Refuse anything larger to avoid allocating for a malicious length.
+const MAX_CONTROL_FRAME_SIZE: u32 = 64 * 1024;
+
+fn codec() -> impl Options {
+    bincode::options().with_limit(MAX_CONTROL_FRAME_SIZE as u64)
+}
+
+/// Serialize and write one frame to the stream.
+pub async fn write_frame<S, T>(stream: &mut S, item: &T) -> Result<()>
+where
+    S: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let content = codec().serialize(item)?;
+
+    stream.write_u32(content.len() as u32).await?;
+    stream.write_all(&content).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// Read exactly one frame from the stream. Bytes after the frame are left untouched.
+pub async fn read_frame<S, T>(stream: &mut S) -> Result<T>
+where
+    S: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    let size = stream.read_u32().await?;
+    if size > MAX_CONTROL_FRAME_SIZE {
+        anyhow::bail!("Control frame too large: {} bytes", size);
+    }
+    let mut content = vec![0u8; size as usize];
+    stream.read_exact(&mut content).await?;
+
+    Ok(codec().deserialize(&content)?)
+}
@@ -0,0 +1,125 @@
+//! Hello exchange performed right after the connection is established, so that the server knows
+//! what this agent build is able to decode before it sends any request.
+//!
+//! The exchange goes like this, and both sides are authenticated before the multiplexed stream
+//! starts:
+//! 1. agent -> server: `Hello` with agent nonce.
+//! 2. server -> agent: `Challenge` with server nonce and server proof.
+//! 3. agent -> server: `ChallengeResponse` with agent proof.
+//! 4. server -> agent: `HelloAck`, with the codec picked for the multiplexed stream.
+
+use serde::{Deserialize, Serialize};
+use strum::VariantNames;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::error::{AgentError, Result};
+use crate::service::RequestPayload;
+
+use super::auth;
+use super::codec::{self, Codec};
+use super::frame::{read_frame, write_frame};
+
+/// Version of the agent <-> server protocol. Bump it on incompatible frame changes.
+pub const PROTOCOL_VERSION: u16 = 8;
+/// Version of this agent build.
+pub const AGENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The first frame sent by agent.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Hello {
+    /// Protocol version the agent speaks.
+    pub protocol_version: u16,
+    /// Agent build version.
+    pub agent_version: String,
+    /// Node name, from `AgentConfig.name`.
+    pub node: String,
+    /// `RequestPayload` variant names the agent can handle.
+    pub capabilities: Vec<String>,
+    /// Random nonce for server to sign.
+    pub nonce: Vec<u8>,
+    /// Codecs agent supports after handshake, in preference order.
+    pub codecs: Vec<Codec>,
+}
+
+/// Server proof of the shared secret, and a nonce for agent to sign.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Challenge {
+    /// Random nonce for agent to sign.
+    pub nonce: Vec<u8>,
+    /// HMAC over both nonces by server.
+    pub proof: Vec<u8>,
+}
+
+/// Agent proof of the shared secret.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChallengeResponse {
+    /// HMAC over both nonces by agent.
+    pub proof: Vec<u8>,
+}
+
+/// Server answer to `Hello`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HelloAck {
+    /// Protocol version the server speaks.
+    pub protocol_version: u16,
+    /// Whether the server accepts this agent.
+    pub accepted: bool,
+    /// Reason of rejection, or any message from server.
+    pub message: String,
+    /// Codec picked by server. Bincode if not set.
+    pub codec: Option<Codec>,
+}
+
+impl Hello {
+    pub fn new(node: &str) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            agent_version: AGENT_VERSION.to_string(),
+            node: node.to_string(),
+            capabilities: supported_request_kinds(),
+            nonce: auth::generate_nonce(),
+            codecs: codec::preferred(),
+        }
+    }
+}
+
+/// Names of request kinds supported by the agent.
+pub fn supported_request_kinds() -> Vec<String> {
+    RequestPayload::VARIANTS.iter().map(|s| s.to_string()).collect()
+}
+
+/// Send `Hello`, authenticate each other with the shared secret, and wait for the server to
+/// accept this agent.
+pub async fn perform<S>(stream: &mut S, node: &str, secret: &str) -> Result<HelloAck>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let hello = Hello::new(node);
+    write_frame(stream, &hello).await?;
+
+    let challenge: Challenge = read_frame(stream).await?;
+    if !auth::verify_server_proof(secret, &hello.nonce, &challenge.nonce, &challenge.proof) {
+        return Err(AgentError::Unauthenticated.into());
+    }
+    let response = ChallengeResponse {
+        proof: auth::agent_proof(secret, &hello.nonce, &challenge.nonce),
+    };
+    write_frame(stream, &response).await?;
+
+    let ack: HelloAck = read_frame(stream).await?;
+
+    if !ack.accepted {
+        return Err(AgentError::Handshake(ack.message).into());
+    }
+    if ack.protocol_version != PROTOCOL_VERSION {
+        let message = format!(
+            "protocol version mismatch, agent {} and server {}",
+            PROTOCOL_VERSION, ack.protocol_version
+        );
+        return Err(AgentError::Handshake(message).into());
+    }
+    if matches!(ack.codec, Some(c) if !hello.codecs.contains(&c)) {
+        return Err(AgentError::Handshake("server picked an unsupported codec".to_string()).into());
+    }
+    Ok(ack)
+}
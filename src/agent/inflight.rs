@@ -0,0 +1,72 @@
+//! Requests being processed on a connection, indexed by tag, so that they can be cancelled.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use futures::future::{AbortHandle, AbortRegistration};
+
+use crate::metrics;
+
+#[derive(Debug, Default)]
+struct Registry {
+    /// Increasing id to tell apart requests with a reused tag.
+    next_id: u64,
+    requests: HashMap<u32, (u64, AbortHandle)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InFlight(Arc<Mutex<Registry>>);
+
+/// Remove the request from registry when dropped.
+pub struct InFlightGuard {
+    in_flight: InFlight,
+    tag: u32,
+    id: u64,
+}
+
+impl InFlight {
+    /// Register a request. Use the returned registration to make its future abortable, and keep
+    /// the guard until the request is finished.
+    pub fn register(&self, tag: u32) -> (AbortRegistration, InFlightGuard) {
+        let (handle, registration) = AbortHandle::new_pair();
+        let mut registry = self.0.lock().unwrap();
+
+        let id = registry.next_id;
+        registry.next_id += 1;
+        registry.requests.insert(tag, (id, handle));
+        metrics::IN_FLIGHT.inc();
+
+        let guard = InFlightGuard {
+            in_flight: self.clone(),
+            tag,
+            id,
+        };
+        (registration, guard)
+    }
+
+    /// Abort the request with the tag. Return false if no such request.
+    pub fn cancel(&self, tag: u32) -> bool {
+        if let Some((_, handle)) = self.0.lock().unwrap().requests.remove(&tag) {
+            handle.abort();
+            return true;
+        }
+        false
+    }
+
+    /// Count of requests being processed.
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().requests.len()
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let mut registry = self.in_flight.0.lock().unwrap();
+
+        metrics::IN_FLIGHT.dec();
+        // The tag may be reused by a new request after this one is cancelled.
+        if matches!(registry.requests.get(&self.tag), Some((id, _)) if *id == self.id) {
+            registry.requests.remove(&self.tag);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! Concurrency limits of requests, shared by all connections.
+//!
+//! A request takes a slot of its campus system first, if any, and then a global slot, so that
+//! requests to a slow system do not hold global slots while waiting. Every request takes a global
+//! slot, except sub-requests of a batch, which run under the slot of the batch to avoid a batch
+//! waiting for its own sub-requests. Requests beyond the queue length are
+//! rejected with `ActionError::Busy` instead of stalling the connection, because heartbeat and
+//! cancel frames are read from the same stream as requests.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use strum::IntoEnumIterator;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::config::{LimitConfig, CONFIG};
+use crate::service::{ActionError, CampusSystem};
+
+lazy_static! {
+    /// Limiter of the agent process.
+    pub static ref LIMITER: Limiter = Limiter::new(&CONFIG.limit);
+}
+
+pub struct Limiter {
+    global: Semaphore,
+    systems: HashMap<CampusSystem, Semaphore>,
+    /// Requests waiting for slots now.
+    waiting: AtomicUsize,
+    /// Max waiting requests.
+    queue: usize,
+}
+
+/// Slots taken by a request, released when dropped.
+pub struct Permit {
+    _system: Option<SemaphorePermit<'static>>,
+    _global: Option<SemaphorePermit<'static>>,
+}
+
+/// Decrease waiting count when dropped, including the case the request is cancelled.
+struct WaitingGuard<'a>(&'a AtomicUsize);
+
+impl Drop for WaitingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Limiter {
+    pub fn new(config: &LimitConfig) -> Self {
+        let systems = CampusSystem::iter()
+            .map(|system| (system, Semaphore::new(config.of(system))))
+            .collect();
+
+        Self {
+            global: Semaphore::new(config.global),
+            systems,
+            waiting: AtomicUsize::new(0),
+            queue: config.queue,
+        }
+    }
+
+    /// Take slots for a request to the system if any, and a global one if `global` is set. Wait if
+    /// no slot available, or return `ActionError::Busy` if too many requests are already waiting.
+    pub async fn acquire(
+        &'static self,
+        system: Option<CampusSystem>,
+        global: bool,
+    ) -> Result<Permit, ActionError> {
+        let system_semaphore = system.map(|system| &self.systems[&system]);
+        let global_semaphore = if global { Some(&self.global) } else { None };
+
+        let system_permit = system_semaphore.map(Semaphore::try_acquire).transpose();
+        if let Ok(system_permit) = system_permit {
+            if let Ok(global_permit) = global_semaphore.map(Semaphore::try_acquire).transpose() {
+                return Ok(Permit {
+                    _system: system_permit,
+                    _global: global_permit,
+                });
+            }
+        }
+        if self.waiting.fetch_add(1, Ordering::SeqCst) >= self.queue {
+            self.waiting.fetch_sub(1, Ordering::SeqCst);
+            return Err(ActionError::Busy);
+        }
+        let _guard = WaitingGuard(&self.waiting);
+
+        // Semaphores are never closed.
+        let system_permit = match system_semaphore {
+            Some(semaphore) => Some(semaphore.acquire().await.unwrap()),
+            None => None,
+        };
+        let global_permit = match global_semaphore {
+            Some(semaphore) => Some(semaphore.acquire().await.unwrap()),
+            None => None,
+        };
+        Ok(Permit {
+            _system: system_permit,
+            _global: global_permit,
+        })
+    }
+
+    /// Count of requests waiting for slots.
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Limiter;
+    use crate::config::LimitConfig;
+    use crate::service::{ActionError, CampusSystem};
+
+    #[tokio::test]
+    async fn test_busy_when_queue_full() {
+        let config = LimitConfig {
+            jwxt: 1,
+            queue: 0,
+            ..LimitConfig::default()
+        };
+        let limiter: &'static Limiter = Box::leak(Box::new(Limiter::new(&config)));
+
+        let permit = limiter.acquire(Some(CampusSystem::Jwxt), true).await.unwrap();
+        // Other systems are not affected.
+        assert!(limiter.acquire(Some(CampusSystem::Card), true).await.is_ok());
+        assert!(matches!(
+            limiter.acquire(Some(CampusSystem::Jwxt), true).await,
+            Err(ActionError::Busy)
+        ));
+
+        drop(permit);
+        assert!(limiter.acquire(Some(CampusSystem::Jwxt), true).await.is_ok());
+        assert_eq!(limiter.waiting(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_global_limit() {
+        let config = LimitConfig {
+            global: 1,
+            queue: 0,
+            ..LimitConfig::default()
+        };
+        let limiter: &'static Limiter = Box::leak(Box::new(Limiter::new(&config)));
+
+        // Requests without a campus system take the global slot too.
+        let permit = limiter.acquire(None, true).await.unwrap();
+        assert!(matches!(
+            limiter.acquire(Some(CampusSystem::Card), true).await,
+            Err(ActionError::Busy)
+        ));
+        // Sub-requests of a batch run under the slot of the batch.
+        assert!(limiter.acquire(Some(CampusSystem::Card), false).await.is_ok());
+
+        drop(permit);
+        assert!(limiter.acquire(None, true).await.is_ok());
+    }
+}
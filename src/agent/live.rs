@@ -0,0 +1,36 @@
+//! Settings applied live when config is reloaded, see `reload` module.
+
+use std::sync::RwLock;
+
+use crate::config::CONFIG;
+use crate::net::proxy::Routes;
+
+/// Node name and HTTP client built with the proxy, taken from the reloaded config.
+#[derive(Clone)]
+pub struct LiveSettings {
+    pub node: String,
+    pub client: reqwest::Client,
+    /// Routes the client is built with.
+    pub routes: Routes,
+}
+
+lazy_static! {
+    /// Unset until config is reloaded, so that the values given on startup are used.
+    static ref LIVE: RwLock<Option<LiveSettings>> = RwLock::new(None);
+}
+
+pub fn set(settings: LiveSettings) {
+    *LIVE.write().unwrap() = Some(settings);
+}
+
+pub fn get() -> Option<LiveSettings> {
+    LIVE.read().unwrap().clone()
+}
+
+/// Routes of the client in use.
+pub fn routes() -> Routes {
+    match get() {
+        Some(settings) => settings.routes,
+        None => Routes::new(&CONFIG),
+    }
+}
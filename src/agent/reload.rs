@@ -0,0 +1,114 @@
+//! Reload config on SIGHUP or when the file is modified, and apply the node name, proxies, DNS
+//! and connection count without restart. Other settings take effect after restart.
+
+use std::time::{Duration, SystemTime};
+
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{info, warn};
+
+use crate::config::{self, Config, DnsConfig, ProxyConfig, CONFIG};
+use crate::error::Result;
+use crate::net;
+
+use super::live::{self, LiveSettings};
+use super::worker::PoolHandle;
+
+/// Interval to check modification time of the config file.
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Settings applied live.
+#[derive(PartialEq)]
+struct Settings {
+    node: String,
+    proxy: Option<String>,
+    routes: ProxyConfig,
+    dns: DnsConfig,
+    conn: u8,
+}
+
+impl From<&Config> for Settings {
+    fn from(config: &Config) -> Self {
+        Self {
+            node: config.agent.name.clone(),
+            proxy: config.agent.proxy.clone(),
+            routes: config.proxy.clone(),
+            dns: config.dns.clone(),
+            conn: config.server.conn,
+        }
+    }
+}
+
+fn modified_time() -> Option<SystemTime> {
+    std::fs::metadata(config::config_path())
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Apply changed settings. Workers are reconnected one by one if the node name changed, since it's
+/// sent to server on handshake. Pool changes run on the pool thread, not to block the watch loop.
+fn apply(current: &Settings, new: &Settings, config: &Config, pool: &PoolHandle) -> Result<()> {
+    let client_changed =
+        new.proxy != current.proxy || new.routes != current.routes || new.dns != current.dns;
+    if new.node != current.node || client_changed {
+        let client = net::proxy::build_client(config)?;
+
+        live::set(LiveSettings {
+            node: new.node.clone(),
+            client,
+            routes: net::proxy::Routes::new(config),
+        });
+    }
+    if new.node != current.node {
+        info!("Node name changed to {}, reconnecting workers.", new.node);
+        pool.restart();
+    }
+    if new.conn != current.conn {
+        info!("Scale connections from {} to {}.", current.conn, new.conn);
+        pool.scale(new.conn as usize);
+    }
+    Ok(())
+}
+
+/// Watch the config file and SIGHUP, and apply changes to the pool until error.
+pub async fn watch(pool: &PoolHandle) -> Result<()> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+    let mut last_modified = modified_time();
+    let mut current = Settings::from(&*CONFIG);
+
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => info!("SIGHUP received, reloading config."),
+            _ = interval.tick() => {
+                let modified = modified_time();
+                if modified == last_modified {
+                    continue;
+                }
+                last_modified = modified;
+                info!("Config file modified, reloading.");
+            }
+        }
+
+        let mut config = match config::reload() {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("Keep the current config: {:#}", e);
+                continue;
+            }
+        };
+        // Urls of campus systems are built from endpoints on startup, and routes must be matched
+        // by the same hosts.
+        if config.endpoints != CONFIG.endpoints {
+            warn!("Changes of [endpoints] take effect after restart.");
+            config.endpoints = CONFIG.endpoints.clone();
+        }
+        let new = Settings::from(&config);
+        if new == current {
+            continue;
+        }
+        match apply(&current, &new, &config, pool) {
+            Ok(()) => current = new,
+            Err(e) => warn!("Could not apply the new config: {:#}", e),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Connection status of each worker, for reporting.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use strum_macros::{EnumIter, IntoStaticStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, EnumIter, IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum ConnectionState {
+    /// Trying to connect to an endpoint.
+    Connecting,
+    /// Connected and serving requests.
+    Connected,
+    /// Waiting before the next reconnect attempt.
+    Waiting,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkerStatus {
+    pub state: ConnectionState,
+    /// Endpoint connecting or connected to.
+    pub endpoint: Option<String>,
+    /// Consecutive failed reconnect rounds.
+    pub reconnect_attempts: u32,
+}
+
+impl Default for WorkerStatus {
+    fn default() -> Self {
+        Self {
+            state: ConnectionState::Connecting,
+            endpoint: None,
+            reconnect_attempts: 0,
+        }
+    }
+}
+
+lazy_static! {
+    /// Status of workers, indexed by worker id.
+    static ref WORKERS: Mutex<BTreeMap<usize, WorkerStatus>> = Mutex::new(BTreeMap::new());
+}
+
+/// Update status of the worker.
+pub fn update<F>(worker: usize, f: F)
+where
+    F: FnOnce(&mut WorkerStatus),
+{
+    let mut workers = WORKERS.lock().unwrap();
+
+    f(workers.entry(worker).or_default());
+}
+
+/// Remove the worker when it exits.
+pub fn remove(worker: usize) {
+    WORKERS.lock().unwrap().remove(&worker);
+}
+
+/// Get status of all workers.
+pub fn snapshot() -> Vec<(usize, WorkerStatus)> {
+    let workers = WORKERS.lock().unwrap();
+
+    workers.iter().map(|(id, status)| (*id, status.clone())).collect()
+}
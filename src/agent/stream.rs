@@ -0,0 +1,75 @@
+//! Streaming response: a request may deliver its result in several chunk frames, followed by a
+//! final response `ResponsePayload::EndOfStream` carrying the chunk count.
+//!
+//! Chunks are queued in a bounded channel and written by `Channel`, so a producer waits when the
+//! connection is slow, instead of buffering the whole result.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+
+use crate::error::{AgentError, Result};
+use crate::service::{ResponseChunk, ResponsePayload};
+
+use super::channel::AgentFrame;
+use super::Tagged;
+
+/// Max bytes of binary data in a chunk.
+pub const BLOB_CHUNK_SIZE: usize = 64 * 1024;
+/// Max frames queued for sending on a connection.
+pub(super) const QUEUE_SIZE: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct ResponseStream {
+    tag: u32,
+    sender: mpsc::Sender<AgentFrame>,
+    /// Chunks sent.
+    count: Arc<AtomicU32>,
+}
+
+impl ResponseStream {
+    pub(super) fn new(tag: u32, sender: mpsc::Sender<AgentFrame>) -> Self {
+        Self {
+            tag,
+            sender,
+            count: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    /// Send a chunk. Wait if too many frames are queued.
+    pub async fn send(&self, chunk: ResponseChunk) -> Result<()> {
+        let frame = AgentFrame::Chunk(Tagged {
+            v: chunk,
+            tag: self.tag,
+        });
+
+        self.sender
+            .send(frame)
+            .await
+            .map_err(|_| AgentError::Service("Connection closed while streaming".to_string()))?;
+        self.count.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Send binary data in pieces of `BLOB_CHUNK_SIZE`.
+    pub async fn send_blob(&self, name: &str, content: &[u8]) -> Result<()> {
+        let total = content.len() as u64;
+
+        for (index, piece) in content.chunks(BLOB_CHUNK_SIZE).enumerate() {
+            let chunk = ResponseChunk::Blob {
+                name: name.to_string(),
+                offset: (index * BLOB_CHUNK_SIZE) as u64,
+                total,
+                data: piece.to_vec(),
+            };
+            self.send(chunk).await?;
+        }
+        Ok(())
+    }
+
+    /// Final response of the stream.
+    pub fn finish(&self) -> ResponsePayload {
+        ResponsePayload::EndOfStream(self.count.load(Ordering::SeqCst))
+    }
+}
@@ -0,0 +1,69 @@
+//! Byte streams the multiplexed protocol can run over.
+
+use reqwest::Url;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+
+use crate::config::{TlsConfig, CONFIG};
+use crate::error::{AgentError, Result};
+
+mod tls;
+mod ws;
+
+/// A connected, bidirectional byte stream to kite-server.
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T> Transport for T where T: AsyncRead + AsyncWrite + Unpin + Send {}
+
+pub type BoxedTransport = Box<dyn Transport>;
+
+/// Connect to server, the transport is selected by scheme of the address:
+/// - `ws://host/path` or `wss://host/path`, WebSocket and WebSocket over TLS.
+/// - `host:port` or `tcp://host:port`, raw TCP. TLS is applied if `[server.tls]` is set.
+pub async fn connect(server_address: &str) -> Result<BoxedTransport> {
+    if server_address.starts_with("ws://") || server_address.starts_with("wss://") {
+        return connect_websocket(server_address).await;
+    }
+    let server_address = server_address.trim_start_matches("tcp://");
+    let socket = TcpStream::connect(server_address)
+        .await
+        .map_err(|_| AgentError::ConnectionFailure)?;
+
+    if let Some(tls_config) = &CONFIG.server.tls {
+        let stream = tls::connect(socket, host_of(server_address), tls_config).await?;
+        return Ok(Box::new(stream));
+    }
+    Ok(Box::new(socket))
+}
+
+async fn connect_websocket(url: &str) -> Result<BoxedTransport> {
+    let parsed_url = Url::parse(url)?;
+    let host = parsed_url
+        .host_str()
+        .ok_or_else(|| AgentError::WebSocket(format!("No host in {}", url)))?;
+    // Default port of ws and wss are known to `Url`.
+    let port = parsed_url.port_or_known_default().unwrap_or(80);
+
+    let socket = TcpStream::connect((host, port))
+        .await
+        .map_err(|_| AgentError::ConnectionFailure)?;
+
+    if parsed_url.scheme() == "wss" {
+        let default_config = TlsConfig::default();
+        let tls_config = CONFIG.server.tls.as_ref().unwrap_or(&default_config);
+        let stream = tls::connect(socket, host, tls_config).await?;
+
+        return Ok(Box::new(ws::connect(url, stream).await?));
+    }
+    Ok(Box::new(ws::connect(url, socket).await?))
+}
+
+/// Get host part from an address like "localhost:8443".
+pub(crate) fn host_of(address: &str) -> &str {
+    match address.rsplit_once(':') {
+        Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+        _ => address,
+    }
+    .trim_start_matches('[')
+    .trim_end_matches(']')
+}
@@ -0,0 +1,158 @@
+//! TLS layer between agent and kite-server, with optional certificate pinning.
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore, ServerName};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+use crate::config::TlsConfig;
+use crate::error::{AgentError, Result};
+
+/// Verify server certificate by CA chain, and then compare its fingerprint with the pinned one.
+struct PinnedVerifier {
+    /// Normalized SHA-256 fingerprint in lower-case hex. No pinning if `None`.
+    fingerprint: Option<String>,
+    /// CA based verifier. Skipped if only fingerprint is configured.
+    web_pki: Option<WebPkiVerifier>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        if let Some(expected) = &self.fingerprint {
+            if &fingerprint_of(&end_entity.0) != expected {
+                return Err(rustls::Error::General(
+                    "Server certificate does not match the pinned fingerprint".to_string(),
+                ));
+            }
+        }
+        if let Some(verifier) = &self.web_pki {
+            verifier.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                scts,
+                ocsp_response,
+                now,
+            )?;
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Calculate SHA-256 fingerprint of a DER certificate, in lower-case hex.
+fn fingerprint_of(der: &[u8]) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, der);
+
+    digest.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Remove separators and convert the configured fingerprint to lower case.
+fn normalize_fingerprint(fingerprint: &str) -> String {
+    fingerprint
+        .chars()
+        .filter(|ch| ch.is_ascii_hexdigit())
+        .map(|ch| ch.to_ascii_lowercase())
+        .collect()
+}
+
+fn load_certificates(path: &str) -> Result<Vec<Certificate>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader)?;
+
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn load_private_key(path: &str) -> Result<PrivateKey> {
+    use rustls_pemfile::Item;
+
+    let mut reader = BufReader::new(File::open(path)?);
+    for item in rustls_pemfile::read_all(&mut reader)? {
+        match item {
+            Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key) => return Ok(PrivateKey(key)),
+            _ => continue,
+        }
+    }
+    Err(AgentError::Tls(format!("No private key found in {}", path)).into())
+}
+
+fn build_client_config(config: &TlsConfig) -> Result<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    match &config.ca {
+        Some(ca_file) => {
+            for cert in load_certificates(ca_file)? {
+                roots.add(&cert)?;
+            }
+        }
+        None => roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                ta.subject,
+                ta.spki,
+                ta.name_constraints,
+            )
+        })),
+    }
+    // A pinned self-signed certificate would never pass the CA check, so skip it when only the
+    // fingerprint is given.
+    let web_pki = if config.fingerprint.is_some() && config.ca.is_none() {
+        None
+    } else {
+        Some(WebPkiVerifier::new(roots, None))
+    };
+    let verifier = PinnedVerifier {
+        fingerprint: config.fingerprint.as_deref().map(normalize_fingerprint),
+        web_pki,
+    };
+
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(verifier));
+    let client_config = match (&config.cert, &config.key) {
+        (Some(cert), Some(key)) => {
+            builder.with_client_auth_cert(load_certificates(cert)?, load_private_key(key)?)?
+        }
+        (None, None) => builder.with_no_client_auth(),
+        _ => return Err(AgentError::Tls("Client cert and key must be set together".to_string()).into()),
+    };
+    Ok(client_config)
+}
+
+/// Do TLS handshake on a socket connected to `host`. Any verification failure aborts the connection.
+pub async fn connect(socket: TcpStream, host: &str, config: &TlsConfig) -> Result<TlsStream<TcpStream>> {
+    let client_config = build_client_config(config)?;
+    let domain = config.domain.as_deref().unwrap_or(host);
+    let server_name = ServerName::try_from(domain)
+        .map_err(|_| AgentError::Tls(format!("Invalid server name: {}", domain)))?;
+
+    let connector = TlsConnector::from(Arc::new(client_config));
+    let stream = connector
+        .connect(server_name, socket)
+        .await
+        .map_err(|e| AgentError::Tls(e.to_string()))?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_normalize_fingerprint() {
+        use super::normalize_fingerprint;
+
+        assert_eq!(normalize_fingerprint("3A:5F:0b"), "3a5f0b");
+        assert_eq!(normalize_fingerprint(" 3a5f0b "), "3a5f0b");
+    }
+}
@@ -0,0 +1,110 @@
+//! WebSocket transport, for servers behind an HTTP reverse proxy.
+//!
+//! Bytes written by the upper layer are sent as binary messages, and payloads of received binary
+//! messages are read back as a continuous byte stream. Frames keep their length prefix, so message
+//! boundaries are not significant to either side.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::{ready, Sink, Stream};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+use crate::error::{AgentError, Result};
+
+pub struct WsTransport<S> {
+    inner: WebSocketStream<S>,
+    /// Payload of the last received message which is not read yet.
+    pending: Vec<u8>,
+    /// Read position in `pending`.
+    position: usize,
+}
+
+/// Do WebSocket handshake with given url on a connected stream.
+pub async fn connect<S>(url: &str, stream: S) -> Result<WsTransport<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (inner, _) = tokio_tungstenite::client_async(url, stream)
+        .await
+        .map_err(|e| AgentError::WebSocket(e.to_string()))?;
+
+    Ok(WsTransport {
+        inner,
+        pending: Vec::new(),
+        position: 0,
+    })
+}
+
+fn to_io_error(e: tokio_tungstenite::tungstenite::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+impl<S> AsyncRead for WsTransport<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            if self.position < self.pending.len() {
+                let count = std::cmp::min(buf.remaining(), self.pending.len() - self.position);
+                let start = self.position;
+
+                buf.put_slice(&self.pending[start..start + count]);
+                self.position += count;
+                return Poll::Ready(Ok(()));
+            }
+            match ready!(Pin::new(&mut self.inner).poll_next(cx)) {
+                Some(Ok(Message::Binary(content))) => {
+                    self.pending = content;
+                    self.position = 0;
+                }
+                // Ping is answered by tungstenite automatically.
+                Some(Ok(Message::Ping(_)))
+                | Some(Ok(Message::Pong(_)))
+                | Some(Ok(Message::Frame(_))) => continue,
+                Some(Ok(Message::Text(_))) => {
+                    let e = io::Error::new(io::ErrorKind::InvalidData, "Unexpected text message");
+                    return Poll::Ready(Err(e));
+                }
+                // Closed by peer, return EOF.
+                Some(Ok(Message::Close(_))) | None => return Poll::Ready(Ok(())),
+                Some(Err(e)) => return Poll::Ready(Err(to_io_error(e))),
+            }
+        }
+    }
+}
+
+impl<S> AsyncWrite for WsTransport<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut inner = Pin::new(&mut self.inner);
+
+        ready!(inner.as_mut().poll_ready(cx)).map_err(to_io_error)?;
+        inner
+            .start_send(Message::Binary(buf.to_vec()))
+            .map_err(to_io_error)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx).map_err(to_io_error)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx).map_err(to_io_error)
+    }
+}
@@ -0,0 +1,247 @@
+//! Worker thread keeping one connection to server.
+
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+use crate::config::CONFIG;
+
+use super::backoff::Backoff;
+use super::status::{self, ConnectionState};
+use super::{connect, serve_until, SharedData};
+use crate::metrics;
+
+/// Max time to wait for a restarted worker to connect, before restarting the next one.
+const RESTART_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Signal to stop a worker, by dropping the sender.
+type StopSignal = watch::Receiver<()>;
+
+fn is_stopped(stop: &StopSignal) -> bool {
+    stop.has_changed().is_err()
+}
+
+/// Wait until the sender of `stop` is dropped.
+async fn stopped(mut stop: StopSignal) {
+    while stop.changed().await.is_ok() {}
+}
+
+/// Try server endpoints in priority order, and serve on the first one connected.
+/// Return true if a connection has been established.
+fn connect_and_serve(
+    runtime: &tokio::runtime::Runtime,
+    worker: usize,
+    data: &SharedData,
+    stop: &StopSignal,
+) -> bool {
+    for endpoint in CONFIG.server.endpoints() {
+        let mut data = data.clone();
+
+        if is_stopped(stop) {
+            return false;
+        }
+        data.refresh();
+        status::update(worker, |s| {
+            s.state = ConnectionState::Connecting;
+            s.endpoint = Some(endpoint.to_string());
+        });
+        let stop = stop.clone();
+        // Run on current thread.
+        let served = runtime.block_on(async {
+            let local = tokio::task::LocalSet::new();
+
+            // Run the local task set.
+            local
+                .run_until(async move {
+                    let run_result = tokio::task::spawn_local(async move {
+                        let connecting = connect(endpoint, &data.node);
+                        let connection = tokio::select! {
+                            connected = connecting => match connected {
+                                Ok(connection) => connection,
+                                Err(e) => {
+                                    warn!(%endpoint, "Could not connect: {}", e);
+                                    return false;
+                                }
+                            },
+                            _ = stopped(stop.clone()) => return false,
+                        };
+                        status::update(worker, |s| s.state = ConnectionState::Connected);
+
+                        // In-flight requests are finished before closing on stop.
+                        serve_until(connection, data, stopped(stop))
+                            .await
+                            .unwrap_or_else(|e| warn!("Connection lost: {}", e));
+                        true
+                    })
+                    .await;
+                    match run_result {
+                        Ok(served) => served,
+                        Err(e) => {
+                            error!("Tokio local set run until fails: {}", e);
+                            false
+                        }
+                    }
+                })
+                .await
+        });
+        if served {
+            return true;
+        }
+    }
+    false
+}
+
+/// Keep a connection to server on the current thread, reconnecting with backoff when it's lost.
+/// Return when the sender of `stop` is dropped.
+pub fn run(worker: usize, data: SharedData, mut stop: StopSignal) {
+    let _span = tracing::info_span!("worker", id = worker).entered();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Fail to create runtime.");
+    let mut backoff = Backoff::new(
+        Duration::from_secs(CONFIG.server.reconnect_min),
+        Duration::from_secs(CONFIG.server.reconnect_max),
+    );
+
+    loop {
+        // Start over from the shortest delay once the link has been up.
+        if connect_and_serve(&runtime, worker, &data, &stop) {
+            backoff.reset();
+        }
+        if is_stopped(&stop) {
+            break;
+        }
+        let delay = backoff.next_delay();
+
+        status::update(worker, |s| {
+            s.state = ConnectionState::Waiting;
+            s.reconnect_attempts = backoff.attempt();
+        });
+        info!(
+            "Reconnect attempt {} in {:.1}s...",
+            backoff.attempt(),
+            delay.as_secs_f32()
+        );
+        let stopped = runtime.block_on(async {
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => false,
+                _ = stop.changed() => true,
+            }
+        });
+        if stopped {
+            break;
+        }
+    }
+    status::remove(worker);
+    metrics::remove_worker(worker);
+    info!("Worker stopped.");
+}
+
+/// Worker threads, one for each connection to server.
+pub struct WorkerPool {
+    data: SharedData,
+    /// Worker threads indexed by id, with senders of their stop signals.
+    workers: Vec<(watch::Sender<()>, JoinHandle<()>)>,
+}
+
+impl WorkerPool {
+    pub fn new(data: SharedData) -> Self {
+        Self {
+            data,
+            workers: Vec::new(),
+        }
+    }
+
+    fn spawn(&self, worker: usize) -> (watch::Sender<()>, JoinHandle<()>) {
+        let (sender, stop) = watch::channel(());
+        let data = self.data.clone();
+
+        (sender, std::thread::spawn(move || run(worker, data, stop)))
+    }
+
+    /// Start or stop workers to keep `count` connections. The last started ones are stopped first.
+    pub fn scale(&mut self, count: usize) {
+        while self.workers.len() < count {
+            let worker = self.spawn(self.workers.len());
+            self.workers.push(worker);
+        }
+        while self.workers.len() > count {
+            let (sender, thread) = self.workers.pop().unwrap();
+
+            drop(sender);
+            let _ = thread.join();
+        }
+    }
+
+    /// Reconnect workers one after another, waiting for each to connect, so that other connections
+    /// are kept meanwhile.
+    pub fn restart(&mut self) {
+        let workers = std::mem::take(&mut self.workers);
+
+        for (worker, (sender, thread)) in workers.into_iter().enumerate() {
+            drop(sender);
+            let _ = thread.join();
+
+            let restarted = self.spawn(worker);
+            self.workers.push(restarted);
+            wait_connected(worker, RESTART_TIMEOUT);
+        }
+    }
+
+    /// Move the pool to a thread, since stopping workers blocks until their connections are
+    /// drained. Changes are applied in order. Workers are stopped once all handles are dropped.
+    pub fn start(mut self) -> PoolHandle {
+        let (sender, receiver) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            for command in receiver {
+                match command {
+                    PoolCommand::Scale(count) => self.scale(count),
+                    PoolCommand::Restart => self.restart(),
+                }
+            }
+        });
+        PoolHandle(sender)
+    }
+}
+
+/// Changes of the pool, applied in order on the pool thread.
+enum PoolCommand {
+    Scale(usize),
+    Restart,
+}
+
+/// Handle to the pool running on its own thread, see `WorkerPool::start`.
+#[derive(Clone)]
+pub struct PoolHandle(mpsc::Sender<PoolCommand>);
+
+impl PoolHandle {
+    /// Start or stop workers to keep `count` connections, without waiting.
+    pub fn scale(&self, count: usize) {
+        let _ = self.0.send(PoolCommand::Scale(count));
+    }
+
+    /// Reconnect workers one after another, without waiting.
+    pub fn restart(&self) {
+        let _ = self.0.send(PoolCommand::Restart);
+    }
+}
+
+/// Block until the worker is connected, or the timeout.
+fn wait_connected(worker: usize, timeout: Duration) {
+    let start = Instant::now();
+
+    while start.elapsed() < timeout {
+        let connected = status::snapshot()
+            .into_iter()
+            .any(|(id, s)| id == worker && s.state == ConnectionState::Connected);
+        if connected {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
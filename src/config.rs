@@ -1,14 +1,50 @@
-use std::{error::Error, fs};
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
 
+use anyhow::Context;
+use once_cell::sync::OnceCell;
 use serde::Deserialize;
+use strum::IntoEnumIterator;
+
+use crate::agent::codec::Codec;
+use crate::error::{AgentError, Result};
+use crate::service::{CampusSystem, RequestPayload};
 
 const DEFAULT_CONFIG_PATH: &str = "kite.toml";
+/// Environment variable to load configuration from another path.
+const CONFIG_PATH_ENV: &str = "KITE_CONFIG";
+/// Prefix of environment variables overriding config keys, such as `KITE_SERVER__ADDR` for
+/// `server.addr`. Sections and keys are separated by double underscores.
+const OVERRIDE_ENV_PREFIX: &str = "KITE_";
 
 lazy_static! {
-    /// Global configuration
-    pub static ref CONFIG: Config = load_config(DEFAULT_CONFIG_PATH)
-        .map_err(|e| panic!("Failed to load {}: {}", DEFAULT_CONFIG_PATH, e))
-        .unwrap();
+    /// Config path set by `init`, which takes precedence over `KITE_CONFIG`.
+    static ref CONFIG_PATH: Mutex<Option<String>> = Mutex::new(None);
+}
+
+/// Config loaded by `init`, or on first use of `CONFIG`.
+static LOADED: OnceCell<Config> = OnceCell::new();
+
+/// Global configuration. Call `init` on startup to report errors, otherwise it's loaded on first
+/// use and panics on error.
+pub static CONFIG: GlobalConfig = GlobalConfig;
+
+pub struct GlobalConfig;
+
+impl Deref for GlobalConfig {
+    type Target = Config;
+
+    fn deref(&self) -> &Config {
+        LOADED.get_or_init(|| {
+            let path = config_path();
+
+            load_config(&path).unwrap_or_else(|e| panic!("Failed to load {}: {:#}", path, e))
+        })
+    }
 }
 
 #[derive(Deserialize)]
@@ -18,14 +54,373 @@ pub struct Config {
     pub agent: AgentConfig,
     /// Server related.
     pub server: ServerConfig,
+    /// Concurrency limits of requests.
+    #[serde(default)]
+    pub limit: LimitConfig,
+    /// TTL of cached responses.
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// Retry of requests to be done eventually.
+    #[serde(default)]
+    pub jobs: JobConfig,
+    /// Base urls of campus systems.
+    #[serde(default)]
+    pub endpoints: EndpointConfig,
+    /// Proxies to campus systems.
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+    /// Name resolution of campus hosts.
+    #[serde(default)]
+    pub dns: DnsConfig,
+    /// Log output.
+    #[serde(default)]
+    pub log: LogConfig,
+    /// Serve Prometheus metrics if set.
+    pub metrics: Option<MetricsConfig>,
+    /// Serve requests over local HTTP JSON API if set.
+    pub gateway: Option<GatewayConfig>,
 }
 
 #[derive(Deserialize)]
 pub struct ServerConfig {
-    /// Server address string. e.g, "wss://localhost/ag"
+    /// Server address string. e.g, "wss://localhost/ag" for WebSocket, or "localhost:8443" for raw TCP.
     pub addr: String,
+    /// Fallback server addresses, tried in order when `addr` is not available.
+    #[serde(default)]
+    pub fallback: Vec<String>,
+    /// Delay before the first reconnect attempt, in seconds.
+    #[serde(default = "default_reconnect_min")]
+    pub reconnect_min: u64,
+    /// Max delay between reconnect attempts, in seconds.
+    #[serde(default = "default_reconnect_max")]
+    pub reconnect_max: u64,
     ///  Max connections to server.
     pub conn: u8,
+    /// Secret shared with server, used to authenticate each other on connecting.
+    pub secret: Option<String>,
+    /// Interval of heartbeat sent to server, in seconds.
+    #[serde(default = "default_heartbeat_interval")]
+    pub heartbeat_interval: u64,
+    /// Disconnect if nothing is received from server in the time, in seconds.
+    #[serde(default = "default_heartbeat_timeout")]
+    pub heartbeat_timeout: u64,
+    /// Encrypt the link to server with TLS if set. Also used by "wss://" addresses.
+    pub tls: Option<TlsConfig>,
+    /// Preferred codec of frames after handshake. Server may pick another one.
+    #[serde(default)]
+    pub codec: Codec,
+}
+
+fn default_reconnect_min() -> u64 {
+    2
+}
+
+fn default_reconnect_max() -> u64 {
+    120
+}
+
+fn default_heartbeat_interval() -> u64 {
+    15
+}
+
+fn default_heartbeat_timeout() -> u64 {
+    45
+}
+
+impl ServerConfig {
+    /// All server endpoints in priority order.
+    pub fn endpoints(&self) -> Vec<&str> {
+        std::iter::once(self.addr.as_str())
+            .chain(self.fallback.iter().map(String::as_str))
+            .collect()
+    }
+}
+
+#[derive(Default, Deserialize)]
+pub struct TlsConfig {
+    /// Server name used for SNI and certificate verification. Host in `addr` by default.
+    pub domain: Option<String>,
+    /// CA certificate file in PEM format. Public web CAs are trusted if not set.
+    pub ca: Option<String>,
+    /// SHA-256 fingerprint of the server certificate in hex, colons allowed. When set, the server
+    /// certificate must match it exactly.
+    pub fingerprint: Option<String>,
+    /// Client certificate chain file in PEM format, sent to server for mutual TLS.
+    pub cert: Option<String>,
+    /// Private key file in PEM format for the client certificate.
+    pub key: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct LimitConfig {
+    /// Max requests processed at the same time, shared by all connections.
+    pub global: usize,
+    /// Max requests waiting for a free slot. More requests are rejected as busy.
+    pub queue: usize,
+    /// Max requests to each campus system at the same time.
+    pub authserver: usize,
+    pub jwxt: usize,
+    pub sc: usize,
+    pub card: usize,
+    pub library: usize,
+}
+
+impl LimitConfig {
+    /// Limit of the campus system.
+    pub fn of(&self, system: CampusSystem) -> usize {
+        match system {
+            CampusSystem::AuthServer => self.authserver,
+            CampusSystem::Jwxt => self.jwxt,
+            CampusSystem::SecondClass => self.sc,
+            CampusSystem::Card => self.card,
+            CampusSystem::Library => self.library,
+        }
+    }
+}
+
+impl Default for LimitConfig {
+    fn default() -> Self {
+        Self {
+            global: 32,
+            queue: 64,
+            authserver: 4,
+            jwxt: 8,
+            sc: 8,
+            card: 4,
+            library: 8,
+        }
+    }
+}
+
+/// TTL of cached responses in seconds, by request kind such as "search_library". Responses of
+/// kinds not listed are not cached.
+#[derive(Deserialize)]
+pub struct CacheConfig(HashMap<String, u64>);
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        let ttl = [
+            ("search_library", 600),
+            ("activity_list", 300),
+            ("activity_detail", 3600),
+            ("major_list", 86400),
+        ];
+
+        Self(ttl.iter().map(|(kind, ttl)| (kind.to_string(), *ttl)).collect())
+    }
+}
+
+impl CacheConfig {
+    /// TTL of the request kind, `None` if it's not cached.
+    pub fn ttl(&self, kind: &str) -> Option<Duration> {
+        self.0
+            .iter()
+            .find(|(name, _)| RequestPayload::kind_by_name(name) == Some(kind))
+            .filter(|(_, ttl)| **ttl > 0)
+            .map(|(_, ttl)| Duration::from_secs(*ttl))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct JobConfig {
+    /// Max attempts of a job, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry, in seconds.
+    pub retry_min: u64,
+    /// Max delay between retries, in seconds.
+    pub retry_max: u64,
+    /// Time to keep results of finished jobs for polling, in seconds.
+    pub keep: u64,
+}
+
+impl Default for JobConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 12,
+            retry_min: 30,
+            retry_max: 1800,
+            keep: 86400,
+        }
+    }
+}
+
+/// Base urls of campus systems, without the trailing slash. Point them to mirrors or local
+/// stand-in servers if needed.
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct EndpointConfig {
+    pub authserver: String,
+    pub jwxt: String,
+    pub sc: String,
+    pub card: String,
+    pub library: String,
+    /// myportal, visited before the card system to take the session.
+    pub portal: String,
+}
+
+impl Default for EndpointConfig {
+    fn default() -> Self {
+        Self {
+            authserver: "https://authserver.sit.edu.cn".to_string(),
+            jwxt: "http://jwxt.sit.edu.cn".to_string(),
+            sc: "http://sc.sit.edu.cn".to_string(),
+            card: "http://card.sit.edu.cn".to_string(),
+            library: "http://210.35.66.106".to_string(),
+            portal: "https://myportal.sit.edu.cn".to_string(),
+        }
+    }
+}
+
+impl EndpointConfig {
+    /// Base url of the campus system.
+    pub fn of(&self, system: CampusSystem) -> &str {
+        match system {
+            CampusSystem::AuthServer => &self.authserver,
+            CampusSystem::Jwxt => &self.jwxt,
+            CampusSystem::SecondClass => &self.sc,
+            CampusSystem::Card => &self.card,
+            CampusSystem::Library => &self.library,
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        let systems = CampusSystem::iter().map(move |system| (system.key(), self.of(system)));
+        systems.chain(std::iter::once(("portal", self.portal.as_str())))
+    }
+}
+
+/// Routing of campus systems to proxies.
+#[derive(Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ProxyConfig {
+    /// Proxies by name.
+    pub servers: HashMap<String, ProxyServer>,
+    /// Route of each campus system, such as `jwxt`, named the same as in `[limit]`. A route is
+    /// "direct", "pool" or a proxy name. Systems not listed take the default route.
+    pub routes: HashMap<String, String>,
+    /// Route of other systems and hosts. `agent.proxy` if not set, or direct.
+    pub default: Option<String>,
+    /// Egress proxies used by the "pool" route, rotated among healthy ones.
+    pub pool: ProxyPoolConfig,
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+pub struct ProxyServer {
+    /// Proxy url, such as "socks5://127.0.0.1:1080".
+    pub url: String,
+    /// Set for proxies intercepting TLS with their own certificates, such as debugging proxies.
+    /// Certificates of hosts routed through it are not verified.
+    #[serde(default)]
+    pub intercepting: bool,
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ProxyPoolConfig {
+    /// Proxy names in `servers`.
+    pub members: Vec<String>,
+    /// Interval of health checks, in seconds.
+    pub check_interval: u64,
+    /// Url requested through each member in health checks. Home of authserver if not set.
+    pub check_url: Option<String>,
+}
+
+impl Default for ProxyPoolConfig {
+    fn default() -> Self {
+        Self {
+            members: Vec::new(),
+            check_interval: 60,
+            check_url: None,
+        }
+    }
+}
+
+/// Name resolution of campus hosts.
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct DnsConfig {
+    /// DNS servers queried in order, such as "10.0.0.1" or "10.0.0.1:5353". The system resolver
+    /// is used if empty.
+    pub servers: Vec<String>,
+    /// Timeout of each query, in seconds.
+    pub timeout: u64,
+    /// Addresses of hosts, taking precedence over DNS.
+    pub hosts: HashMap<String, String>,
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        Self {
+            servers: Vec::new(),
+            timeout: 3,
+            hosts: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Route name of hosts not listed in routes: "direct", "pool", a proxy name, or "agent.proxy".
+    pub fn default_route(&self) -> &str {
+        match &self.proxy.default {
+            Some(route) => route,
+            None if self.agent.proxy.is_some() => "agent.proxy",
+            None => "direct",
+        }
+    }
+
+    /// Route name of the campus system, see `default_route`.
+    pub fn route_of(&self, system: CampusSystem) -> &str {
+        match self.proxy.routes.get(system.key()) {
+            Some(route) => route,
+            None => self.default_route(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// Log filter, such as "info" or "kite_agent=debug,warn". Overridden by `RUST_LOG` if set.
+    pub level: String,
+    /// Output format.
+    pub format: LogFormat,
+    /// Write logs to the file instead of stdout if set.
+    pub file: Option<String>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines.
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+            file: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MetricsConfig {
+    /// Local address to listen on, such as "127.0.0.1:9100".
+    pub addr: String,
+}
+
+#[derive(Deserialize)]
+pub struct GatewayConfig {
+    /// Local address to listen on, such as "127.0.0.1:8080".
+    pub addr: String,
+    /// Bearer token required in requests. Required to listen on addresses other than loopback.
+    pub token: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -36,15 +431,249 @@ pub struct AgentConfig {
     pub db: String,
     /// Proxy string for most connections.
     pub proxy: Option<String>,
+    /// Probe network connectivity and campus systems in `AgentInfo`.
+    #[serde(default = "default_probe")]
+    pub probe: bool,
+}
+
+fn default_probe() -> bool {
+    true
+}
+
+/// Path of the config file in use.
+pub fn config_path() -> String {
+    CONFIG_PATH
+        .lock()
+        .unwrap()
+        .clone()
+        .or_else(|| std::env::var(CONFIG_PATH_ENV).ok())
+        .unwrap_or_else(|| DEFAULT_CONFIG_PATH.to_string())
 }
 
-/// Load the global configuration from DEFAULT_CONFIG_PATH on the startup.
-fn load_config(path: &str) -> Result<Config, Box<dyn Error>> {
+/// Load the global configuration from `path`, or `KITE_CONFIG` and then `kite.toml` if not given,
+/// and check it. Call it on startup before anything reads `CONFIG`.
+pub fn init(path: Option<&str>) -> Result<&'static Config> {
+    if let Some(path) = path {
+        *CONFIG_PATH.lock().unwrap() = Some(path.to_string());
+    }
+    let path = config_path();
+    let config = load_config(&path).with_context(|| format!("Failed to load {}", path))?;
+
+    // Keep the config if `CONFIG` has been used already.
+    Ok(LOADED.get_or_init(|| config))
+}
+
+/// Load the config file again, for settings applied live. `CONFIG` is not changed.
+pub fn reload() -> Result<Config> {
+    let path = config_path();
+
+    load_config(&path).with_context(|| format!("Failed to load {}", path))
+}
+
+/// Parse a value in environment variable. It's taken as a string, such as a numeric secret, unless
+/// the value it overrides is not a string, in which case it's parsed as TOML, such as `4` or `true`.
+fn parse_env_value(value: &str, current: Option<&toml::Value>) -> toml::Value {
+    let raw = toml::Value::String(value.to_string());
+
+    match current {
+        Some(toml::Value::String(_)) | None => raw,
+        Some(_) => toml::from_str::<toml::value::Table>(&format!("v = {}", value))
+            .ok()
+            .and_then(|mut table| table.remove("v"))
+            .unwrap_or(raw),
+    }
+}
+
+/// Apply `KITE_SECTION__KEY` overrides in `vars` to the config table.
+fn apply_env_overrides<I>(table: &mut toml::value::Table, vars: I) -> Result<()>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    for (name, value) in vars {
+        let path = match name.strip_prefix(OVERRIDE_ENV_PREFIX) {
+            Some(path) if path.contains("__") => path.to_lowercase(),
+            _ => continue,
+        };
+        let mut keys: Vec<&str> = path.split("__").collect();
+        let last = keys.pop().unwrap();
+
+        let mut current = &mut *table;
+        for key in keys {
+            current = current
+                .entry(key)
+                .or_insert_with(|| toml::Value::Table(Default::default()))
+                .as_table_mut()
+                .ok_or_else(|| AgentError::Config(format!("{} is not a section", name)))?;
+        }
+        let value = parse_env_value(&value, current.get(last));
+        current.insert(last.to_string(), value);
+    }
+    Ok(())
+}
+
+/// Load the configuration file, with overrides in environment variables.
+fn load_config(path: &str) -> Result<Config> {
     let text = fs::read_to_string(path)?;
-    let toml = toml::from_str(&text)?;
+    let mut table: toml::value::Table = toml::from_str(&text)?;
+
+    apply_env_overrides(&mut table, std::env::vars())?;
+    let config: Config = toml::Value::Table(table).try_into()?;
+    config.validate()?;
+    Ok(config)
+}
+
+impl Config {
+    /// Check values which can't be checked by types.
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |message: String| Err(AgentError::Config(message).into());
 
-    Ok(toml)
+        if self.server.conn == 0 && self.gateway.is_none() {
+            return invalid("server.conn must be positive if [gateway] is not set".to_string());
+        }
+        let server = &self.server;
+        let positive = [
+            ("server.heartbeat_interval", server.heartbeat_interval),
+            ("server.heartbeat_timeout", server.heartbeat_timeout),
+            ("server.reconnect_min", server.reconnect_min),
+            ("dns.timeout", self.dns.timeout),
+            ("proxy.pool.check_interval", self.proxy.pool.check_interval),
+        ];
+        for (key, value) in positive.iter().copied() {
+            if value == 0 {
+                return invalid(format!("{} must be positive", key));
+            }
+        }
+        for kind in self.cache.0.keys() {
+            if RequestPayload::kind_by_name(kind).is_none() {
+                return invalid(format!("cache.{} is not a request kind", kind));
+            }
+        }
+        // A zero limit blocks all requests to the system.
+        let limits = CampusSystem::iter().map(|system| (system.key(), self.limit.of(system)));
+        let limits = std::iter::once(("global", self.limit.global)).chain(limits);
+        for (key, value) in limits {
+            if value == 0 {
+                return invalid(format!("limit.{} must be positive", key));
+            }
+        }
+        if server.reconnect_min > server.reconnect_max {
+            return invalid("server.reconnect_min must not exceed reconnect_max".to_string());
+        }
+        if server.conn > 0 && server.secret.is_none() {
+            return invalid("server.secret must be set if server.conn is positive".to_string());
+        }
+        if self.jobs.retry_min == 0 || self.jobs.retry_min > self.jobs.retry_max {
+            return invalid("jobs.retry_min must be positive and not exceed retry_max".to_string());
+        }
+        if let Some(gateway) = &self.gateway {
+            match gateway.addr.parse::<std::net::SocketAddr>() {
+                Ok(addr) if addr.ip().is_loopback() || gateway.token.is_some() => {}
+                Ok(_) => {
+                    return invalid("gateway.token must be set if addr is not loopback".to_string())
+                }
+                Err(e) => return invalid(format!("gateway.addr {} is invalid: {}", gateway.addr, e)),
+            }
+        }
+        if let Some(proxy) = &self.agent.proxy {
+            if let Err(e) = reqwest::Proxy::all(proxy) {
+                return invalid(format!("agent.proxy {} is invalid: {}", proxy, e));
+            }
+        }
+        for (key, url) in self.endpoints.iter() {
+            match reqwest::Url::parse(url) {
+                Ok(parsed) if parsed.has_host() && !url.ends_with('/') => {}
+                Ok(_) => return invalid(format!("endpoints.{} {} is not a base url", key, url)),
+                Err(e) => return invalid(format!("endpoints.{} {} is invalid: {}", key, url, e)),
+            }
+        }
+        if let Err(e) = crate::net::proxy::Router::new(self) {
+            return invalid(format!("[proxy] is invalid: {:#}", e));
+        }
+        if let Err(e) = crate::net::dns::configure(reqwest::Client::builder(), &self.dns) {
+            return invalid(format!("[dns] is invalid: {:#}", e));
+        }
+        if let Err(e) = check_writable(Path::new(&self.agent.db)) {
+            return invalid(format!("agent.db {} is not writable: {}", self.agent.db, e));
+        }
+        Ok(())
+    }
+}
+
+/// Check that files can be created in the directory, creating it if missing.
+fn check_writable(directory: &Path) -> std::io::Result<()> {
+    let probe = directory.join(".write-test");
+
+    fs::create_dir_all(directory)?;
+    fs::write(&probe, b"")?;
+    fs::remove_file(&probe)
 }
 
 pub(crate) const USERAGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/87.0.4280.88 ' \
 'Safari/537.36 Edg/87.0.664.66";
+
+#[cfg(test)]
+mod test {
+    use super::{apply_env_overrides, Config};
+
+    #[test]
+    fn test_env_overrides() {
+        let mut table = toml::from_str("[server]\naddr = \"localhost:8443\"\nconn = 5").unwrap();
+        let vars = vec![
+            ("KITE_SERVER__ADDR", "10.0.0.1:8443"),
+            ("KITE_SERVER__CONN", "2"),
+            ("KITE_SERVER__SECRET", "123456"),
+            ("KITE_AGENT__NAME", "2024"),
+            ("KITE_AGENT__PROXY", "socks5://127.0.0.1:1080"),
+            ("KITE_CONFIG", "other.toml"),
+            ("HOME", "/root"),
+        ];
+        let vars = vars.into_iter().map(|(k, v)| (k.to_string(), v.to_string()));
+
+        apply_env_overrides(&mut table, vars).unwrap();
+        assert_eq!(table["server"]["addr"].as_str(), Some("10.0.0.1:8443"));
+        assert_eq!(table["server"]["conn"].as_integer(), Some(2));
+        assert_eq!(table["server"]["secret"].as_str(), Some("123456"));
+        assert_eq!(table["agent"]["name"].as_str(), Some("2024"));
+        assert_eq!(table["agent"]["proxy"].as_str(), Some("socks5://127.0.0.1:1080"));
+        assert_eq!(table.len(), 2);
+
+        // A key can't be overridden as a section.
+        let vars = vec![("KITE_SERVER__ADDR__HOST".to_string(), "x".to_string())];
+        assert!(apply_env_overrides(&mut table, vars).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let db = std::env::temp_dir().join("kite-agent-validate");
+        let config = |extra: &str| -> Config {
+            let text = format!(
+                "[agent]\nname = \"test\"\ndb = {:?}\n[server]\naddr = \"localhost:8443\"\nconn = 1\nsecret = \"s\"\n{}",
+                db, extra
+            );
+            toml::from_str(&text).unwrap()
+        };
+
+        assert!(config("").validate().is_ok());
+        let invalid = [
+            "heartbeat_interval = 0",
+            "reconnect_min = 60\nreconnect_max = 10",
+            "[limit]\nglobal = 0",
+            "[limit]\njwxt = 0",
+            "[dns]\ntimeout = 0",
+            "[jobs]\nretry_min = 0",
+            "[cache]\nactivity_detial = 60",
+            "[gateway]\naddr = \"0.0.0.0:8080\"",
+            "[jobs]\nretry_min = 600\nretry_max = 60",
+            "[proxy.pool]\ncheck_interval = 0",
+            "[endpoints]\njwxt = \"http://jwxt.sit.edu.cn/\"",
+        ];
+        for extra in invalid.iter() {
+            assert!(config(extra).validate().is_err(), "{} is accepted", extra);
+        }
+
+        // Workers can't connect without the secret.
+        let mut no_secret = config("");
+        no_secret.server.secret = None;
+        assert!(no_secret.validate().is_err());
+    }
+}
@@ -7,6 +7,22 @@ pub enum AgentError {
     ConnectionFailure,
     #[error("服务错误: {0}")]
     Service(String),
+    #[error("握手失败: {0}")]
+    Handshake(String),
+    #[error("服务端身份验证失败")]
+    Unauthenticated,
+    #[error("未配置与服务端通信的共享密钥")]
+    NoSecret,
+    #[error("TLS 错误: {0}")]
+    Tls(String),
+    #[error("WebSocket 错误: {0}")]
+    WebSocket(String),
+    #[error("心跳超时, 连接已断开")]
+    HeartbeatTimeout,
+    #[error("编解码错误: {0}")]
+    Codec(String),
+    #[error("配置错误: {0}")]
+    Config(String),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -0,0 +1,243 @@
+//! Local HTTP JSON API serving the same requests as the server link, if `[gateway]` is configured.
+//!
+//! `POST /api/<kind>` processes a request of the kind, such as `/api/score` or
+//! `/api/score_detail`, with the JSON body as the request parameter. The response body is the
+//! JSON encoded `ResponsePayload`, or `ErrorResponse` with an HTTP error status. Send header
+//! `Cache-Control: no-cache` to skip cached responses.
+//!
+//! Requests carry credentials of students, so the API is bound to loopback addresses only, unless
+//! `gateway.token` is set. Requests must send the token in `Authorization: Bearer <token>` if set.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use futures::future::AbortHandle;
+use hyper::body::HttpBody;
+use hyper::header::AUTHORIZATION;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use num_traits::ToPrimitive;
+use serde::Serialize;
+use tracing::Instrument;
+
+use crate::agent::{self, SharedData};
+use crate::config::GatewayConfig;
+use crate::error::Result;
+use crate::service::{ActionError, ErrorResponse, RequestPayload};
+
+/// Max size of request body.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// HTTP status of the error response. Other errors are internal server errors.
+fn status_of(e: &ErrorResponse) -> StatusCode {
+    let mapping = [
+        (ActionError::BadRequest, StatusCode::BAD_REQUEST),
+        (ActionError::BadParameter, StatusCode::BAD_REQUEST),
+        (ActionError::LoginFailed, StatusCode::UNAUTHORIZED),
+        (ActionError::NoSessionAvailable, StatusCode::SERVICE_UNAVAILABLE),
+        (ActionError::Busy, StatusCode::SERVICE_UNAVAILABLE),
+        (ActionError::FailToGetCaptcha, StatusCode::BAD_GATEWAY),
+        (ActionError::WrongCaptcha, StatusCode::BAD_GATEWAY),
+        (ActionError::ParsingError, StatusCode::BAD_GATEWAY),
+        (ActionError::Timeout, StatusCode::GATEWAY_TIMEOUT),
+    ];
+
+    mapping
+        .iter()
+        .find(|(error, _)| error.to_u16() == Some(e.code))
+        .map(|(_, status)| *status)
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn json_response<T: Serialize>(status: StatusCode, body: &T) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(body).unwrap()))
+        .unwrap()
+}
+
+fn error_response(status: StatusCode, e: ErrorResponse) -> Response<Body> {
+    json_response(status, &e)
+}
+
+/// Build the request payload from its kind and JSON parameter.
+fn parse_payload(kind: &str, body: &[u8]) -> std::result::Result<RequestPayload, ErrorResponse> {
+    let parameter: serde_json::Value = if body.is_empty() {
+        serde_json::Value::Null
+    } else {
+        serde_json::from_slice(body)?
+    };
+    Ok(RequestPayload::from_json(kind, parameter)?)
+}
+
+/// Whether the request asks to skip cached responses.
+fn is_no_cache(request: &Request<Body>) -> bool {
+    request
+        .headers()
+        .get_all(hyper::header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.split(',').any(|directive| directive.trim() == "no-cache"))
+}
+
+/// Read the body up to `MAX_BODY_SIZE`, rejecting larger ones by `Content-Length` before reading.
+async fn read_body(request: Request<Body>) -> std::result::Result<Vec<u8>, StatusCode> {
+    let length = request
+        .headers()
+        .get(hyper::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<usize>().ok());
+    if matches!(length, Some(length) if length > MAX_BODY_SIZE) {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let mut body = request.into_body();
+    let mut buffer = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|_| StatusCode::BAD_REQUEST)?;
+        if buffer.len() + chunk.len() > MAX_BODY_SIZE {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        buffer.extend_from_slice(&chunk);
+    }
+    Ok(buffer)
+}
+
+/// Check the bearer token of the request, if a token is required. Digests are compared so that
+/// the time taken does not tell how much of the token matches.
+fn is_authorized(request: &Request<Body>, token: Option<&str>) -> bool {
+    let token = match token {
+        Some(token) => token,
+        None => return true,
+    };
+    let digest = |s: &str| ring::digest::digest(&ring::digest::SHA256, s.as_bytes());
+    let sent = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    matches!(sent, Some(sent) if digest(sent).as_ref() == digest(token).as_ref())
+}
+
+async fn handle(request: Request<Body>, mut data: SharedData, token: Option<String>) -> Response<Body> {
+    if !is_authorized(&request, token.as_deref()) {
+        return error_response(StatusCode::UNAUTHORIZED, ActionError::BadRequest.into());
+    }
+    let kind = request
+        .uri()
+        .path()
+        .strip_prefix("/api/")
+        .and_then(RequestPayload::kind_by_name);
+    let kind = match (request.method(), kind) {
+        (&Method::POST, Some(kind)) => kind,
+        _ => return error_response(StatusCode::NOT_FOUND, ActionError::BadRequest.into()),
+    };
+    data.refresh();
+    data.no_cache = is_no_cache(&request);
+    let body = match read_body(request).await {
+        Ok(body) => body,
+        Err(status) => return error_response(status, ActionError::BadRequest.into()),
+    };
+    let payload = match parse_payload(kind, &body) {
+        Ok(payload) => payload,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
+    };
+
+    let span = tracing::info_span!("gateway", kind, account = payload.account());
+    // Requests from gateway are never cancelled.
+    let (_handle, registration) = AbortHandle::new_pair();
+
+    match agent::process(payload, data, None, registration)
+        .instrument(span)
+        .await
+    {
+        Ok(payload) => json_response(StatusCode::OK, &payload),
+        Err(e) => error_response(status_of(&e), e),
+    }
+}
+
+/// Serve the API on the configured address until error.
+pub async fn serve(config: &GatewayConfig, data: SharedData) -> Result<()> {
+    let addr: SocketAddr = config.addr.parse()?;
+    let token = config.token.clone();
+    let make_service = make_service_fn(move |_| {
+        let (data, token) = (data.clone(), token.clone());
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let (data, token) = (data.clone(), token.clone());
+                async move { Ok::<_, Infallible>(handle(request, data, token).await) }
+            }))
+        }
+    });
+
+    tracing::info!("Serve HTTP API on http://{}/api", addr);
+    hyper::Server::try_bind(&addr)?.serve(make_service).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use hyper::{Body, Request, StatusCode};
+
+    use super::{is_authorized, parse_payload, read_body, MAX_BODY_SIZE};
+    use crate::service::RequestPayload;
+
+    #[test]
+    fn test_parse_payload() {
+        assert_eq!(RequestPayload::kind_by_name("score_detail"), Some("ScoreDetail"));
+        assert_eq!(RequestPayload::kind_by_name("ping"), Some("Ping"));
+        assert_eq!(RequestPayload::kind_by_name("unknown"), None);
+
+        let payload = parse_payload("Ping", br#""hello""#).unwrap();
+        assert!(matches!(payload, RequestPayload::Ping(s) if s == "hello"));
+        assert!(parse_payload("Score", b"{}").is_err());
+    }
+
+    #[test]
+    fn test_authorized() {
+        let request = |authorization: Option<&str>| {
+            let builder = Request::post("/api/ping");
+            let builder = match authorization {
+                Some(value) => builder.header("Authorization", value),
+                None => builder,
+            };
+            builder.body(Body::empty()).unwrap()
+        };
+
+        assert!(is_authorized(&request(None), None));
+        assert!(is_authorized(&request(Some("Bearer s3cret")), Some("s3cret")));
+        assert!(!is_authorized(&request(Some("Bearer s3cre")), Some("s3cret")));
+        assert!(!is_authorized(&request(Some("s3cret")), Some("s3cret")));
+        assert!(!is_authorized(&request(None), Some("s3cret")));
+    }
+
+    #[tokio::test]
+    async fn test_read_body() {
+        let request = |length: Option<usize>, body: Body| {
+            let builder = Request::post("/api/ping");
+            let builder = match length {
+                Some(length) => builder.header("Content-Length", length),
+                None => builder,
+            };
+            builder.body(body).unwrap()
+        };
+
+        let body = read_body(request(None, Body::from("\"hello\""))).await;
+        assert_eq!(body.unwrap(), b"\"hello\"");
+
+        // Rejected by the header before reading.
+        let large = request(Some(MAX_BODY_SIZE + 1), Body::empty());
+        assert_eq!(read_body(large).await, Err(StatusCode::PAYLOAD_TOO_LARGE));
+
+        // Rejected while reading chunks without the header.
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            for size in &[MAX_BODY_SIZE, 1] {
+                let _ = sender.send_data(vec![0u8; *size].into()).await;
+            }
+        });
+        let streamed = request(None, body);
+        assert_eq!(read_body(streamed).await, Err(StatusCode::PAYLOAD_TOO_LARGE));
+    }
+}
@@ -0,0 +1,22 @@
+#![allow(dead_code)]
+
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate num_derive;
+#[macro_use]
+extern crate prometheus;
+
+pub use net::SessionStorage;
+
+pub mod agent;
+pub mod config;
+pub mod error;
+pub mod gateway;
+pub mod logger;
+pub mod metrics;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod net;
+pub mod parser;
+pub mod service;
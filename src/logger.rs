@@ -0,0 +1,39 @@
+//! Log output, configured by `[log]` section.
+//!
+//! Each connection runs in a `worker` span and each request in a `request` span with its tag,
+//! kind and account, so that logs of a request, including campus system requests sent by it, can
+//! be picked out together.
+
+use std::path::Path;
+
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::EnvFilter;
+
+use crate::config::{LogFormat, CONFIG};
+
+/// Install the global subscriber. Keep the returned guard until exit so that file logs are flushed.
+pub fn init() -> WorkerGuard {
+    let config = &CONFIG.log;
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.level));
+
+    let (writer, guard) = match &config.file {
+        Some(file) => {
+            let path = Path::new(file);
+            let directory = path.parent().unwrap_or_else(|| Path::new("."));
+            let name = path.file_name().expect("Invalid log file path.");
+
+            tracing_appender::non_blocking(tracing_appender::rolling::never(directory, name))
+        }
+        None => tracing_appender::non_blocking(std::io::stdout()),
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(config.file.is_none());
+
+    match config.format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(true).init(),
+    }
+    guard
+}
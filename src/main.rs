@@ -1,96 +1,80 @@
-#![allow(dead_code)]
-
-#[macro_use]
-extern crate lazy_static;
-#[macro_use]
-extern crate num_derive;
+use std::future::Future;
 
+use structopt::StructOpt;
 use tokio::time::Duration;
+use tracing::error;
+
+use kite_agent::agent::reload;
+use kite_agent::agent::worker::WorkerPool;
+use kite_agent::agent::SharedData;
+use kite_agent::config::{self, CONFIG};
+use kite_agent::net::{self, SessionStorage};
+use kite_agent::{gateway, logger, metrics, service};
+
+/// Run a local server on a new thread.
+fn spawn_server<F>(name: &'static str, server: F)
+where
+    F: Future<Output = anyhow::Result<()>> + Send + 'static,
+{
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Fail to create runtime.");
+
+        if let Err(e) = runtime.block_on(server) {
+            error!("{} server exits: {}", name, e);
+        }
+    });
+}
 
-use agent::{run, SharedData};
-use config::CONFIG;
-use net::SessionStorage;
-
-mod agent;
-mod config;
-mod error;
-mod net;
-mod parser;
-pub mod service;
-
-fn worker_thread(storage: SessionStorage, client: reqwest::Client) {
-    let runtime = tokio::runtime::Builder::new_current_thread()
-        .enable_all()
-        .build()
-        .expect("Fail to create runtime.");
-
-    loop {
-        let storage = storage.clone();
-        let client = client.clone();
-
-        // Run on current thread.
-        runtime.block_on(async move {
-            let remote_server = &CONFIG.server.addr;
-            let node_name = &CONFIG.agent.name;
-
-            let local = tokio::task::LocalSet::new();
-
-            // Run the local task set.
-            local
-                .run_until(async move {
-                    let run_result = tokio::task::spawn_local(async move {
-                        run(
-                            remote_server.clone(),
-                            SharedData {
-                                node: node_name.clone(),
-                                session_store: storage,
-                                client,
-                            },
-                        )
-                        .await
-                        .unwrap_or_else(|e| eprintln!("{}", e));
-                    })
-                    .await;
-                    match run_result {
-                        Ok(_) => (),
-                        Err(e) => println!("Tokio local set run until fails: {}", e),
-                    }
-                })
-                .await;
-            /* KiteService has been aborted now.*/
-        });
-
-        println!("Trying to reconnect...");
-        std::thread::sleep(Duration::from_secs(10));
-    }
+#[derive(StructOpt)]
+#[structopt(name = "kite-agent")]
+struct Opt {
+    /// Config file. `KITE_CONFIG` or kite.toml by default.
+    #[structopt(short, long)]
+    config: Option<String>,
 }
 
 fn main() {
-    let mut builder = reqwest::ClientBuilder::new().redirect(reqwest::redirect::Policy::none());
-
-    if let Some(proxy) = &CONFIG.agent.proxy {
-        let err_msg = "Invalid proxy settings.";
-        builder = builder
-            .proxy(reqwest::Proxy::http(proxy).expect(err_msg))
-            .proxy(reqwest::Proxy::https(proxy).expect(err_msg))
-            .danger_accept_invalid_certs(true);
-
-        println!("Load proxy: {}", proxy);
+    let opt = Opt::from_args();
+    if let Err(e) = config::init(opt.config.as_deref()) {
+        eprintln!("{:#}", e);
+        std::process::exit(1);
     }
-    let http_client = builder.build().expect("Could not init http client.");
+    let _log_guard = logger::init();
+    lazy_static::initialize(&service::report::STARTED_AT);
+    let http_client = net::build_http_client().expect("Could not init http client.");
     let storage = SessionStorage::new().expect("Fail to load SessionStorage.");
-    let mut worker_threads = Vec::new();
-
-    for _ in 0..CONFIG.server.conn {
-        let client = http_client.clone();
-        let storage = storage.clone();
 
-        let worker = std::thread::spawn(move || {
-            worker_thread(storage, client);
-        });
-        worker_threads.push(worker);
+    if let Some(metrics) = &CONFIG.metrics {
+        spawn_server("Metrics", metrics::serve(&metrics.addr, storage.clone()));
+    }
+    let shared_data = SharedData {
+        node: CONFIG.agent.name.clone(),
+        session_store: storage,
+        client: http_client,
+        stream: None,
+        batch: None,
+        no_cache: false,
+    };
+    if let Some(gateway) = &CONFIG.gateway {
+        spawn_server("Gateway", gateway::serve(gateway, shared_data.clone()));
     }
+    spawn_server("Jobs", service::job::run(shared_data.clone()));
+    spawn_server("Cache", service::cache::sweep(shared_data.clone()));
 
+    let workers = WorkerPool::new(shared_data).start();
+    workers.scale(CONFIG.server.conn as usize);
+
+    // Reload config on the main thread.
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Fail to create runtime.");
+    if let Err(e) = runtime.block_on(reload::watch(&workers)) {
+        error!("Config reload stopped: {}", e);
+    }
     loop {
         std::thread::sleep(Duration::from_millis(1000));
     }
@@ -0,0 +1,145 @@
+//! Prometheus metrics, served over HTTP at `/metrics` if `[metrics]` is configured.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use prometheus::{Encoder, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, TextEncoder};
+use strum::IntoEnumIterator;
+
+use crate::agent::status::{self, ConnectionState};
+use crate::error::Result;
+use crate::net::SessionStorage;
+
+lazy_static! {
+    /// Requests received from server, by request kind.
+    pub static ref REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "kite_agent_requests_total",
+        "Requests received, by kind.",
+        &["kind"]
+    )
+    .unwrap();
+    /// Time to process requests, by request kind.
+    pub static ref REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "kite_agent_request_duration_seconds",
+        "Time to process requests, by kind.",
+        &["kind"]
+    )
+    .unwrap();
+    /// Error responses, by `ErrorResponse.code`.
+    pub static ref ERRORS: IntCounterVec = register_int_counter_vec!(
+        "kite_agent_errors_total",
+        "Error responses, by error code.",
+        &["code"]
+    )
+    .unwrap();
+    /// Lookups in response cache, by request kind and result, "hit" or "miss".
+    pub static ref CACHE_LOOKUPS: IntCounterVec = register_int_counter_vec!(
+        "kite_agent_cache_lookups_total",
+        "Lookups in response cache, by kind and result.",
+        &["kind", "result"]
+    )
+    .unwrap();
+    /// HTTP requests sent to campus systems, by domain.
+    pub static ref UPSTREAM_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "kite_agent_upstream_requests_total",
+        "HTTP requests sent to campus systems, by domain.",
+        &["domain"]
+    )
+    .unwrap();
+    /// Login form submissions on authserver, by result.
+    pub static ref LOGIN_ATTEMPTS: IntCounterVec = register_int_counter_vec!(
+        "kite_agent_login_attempts_total",
+        "Login attempts on authserver, by result.",
+        &["result"]
+    )
+    .unwrap();
+    /// Captcha fetched again because the last one could not be recognized.
+    pub static ref CAPTCHA_RETRIES: IntCounter = register_int_counter!(
+        "kite_agent_captcha_retries_total",
+        "Captcha fetched again because the last one could not be recognized."
+    )
+    .unwrap();
+    /// Requests being processed on server connections.
+    pub static ref IN_FLIGHT: IntGauge =
+        register_int_gauge!("kite_agent_in_flight_requests", "Requests being processed.").unwrap();
+    static ref SESSIONS: IntGauge =
+        register_int_gauge!("kite_agent_sessions", "Sessions in storage.").unwrap();
+    static ref WORKER_STATE: IntGaugeVec = register_int_gauge_vec!(
+        "kite_agent_worker_state",
+        "Connection state of each worker, 1 for the current state.",
+        &["worker", "state"]
+    )
+    .unwrap();
+    static ref RECONNECT_ATTEMPTS: IntGaugeVec = register_int_gauge_vec!(
+        "kite_agent_reconnect_attempts",
+        "Consecutive failed reconnect rounds of each worker.",
+        &["worker"]
+    )
+    .unwrap();
+}
+
+/// Remove series of a stopped worker, so that it's not reported with its last state.
+pub fn remove_worker(worker: usize) {
+    let worker = worker.to_string();
+
+    for state in ConnectionState::iter() {
+        let _ = WORKER_STATE.remove_label_values(&[&worker, state.into()]);
+    }
+    let _ = RECONNECT_ATTEMPTS.remove_label_values(&[&worker]);
+}
+
+/// Refresh gauges read from other modules, and encode all metrics.
+fn gather(storage: &SessionStorage) -> Vec<u8> {
+    SESSIONS.set(storage.len() as i64);
+    for (worker, status) in status::snapshot() {
+        let worker = worker.to_string();
+
+        for state in ConnectionState::iter() {
+            let value = (state == status.state) as i64;
+            WORKER_STATE
+                .with_label_values(&[&worker, state.into()])
+                .set(value);
+        }
+        RECONNECT_ATTEMPTS
+            .with_label_values(&[&worker])
+            .set(status.reconnect_attempts as i64);
+    }
+
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .unwrap();
+    buffer
+}
+
+async fn handle(request: Request<Body>, storage: SessionStorage) -> Response<Body> {
+    if request.method() != Method::GET || request.uri().path() != "/metrics" {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return response;
+    }
+    Response::builder()
+        .header(hyper::header::CONTENT_TYPE, TextEncoder::new().format_type())
+        .body(Body::from(gather(&storage)))
+        .unwrap()
+}
+
+/// Serve metrics on the address until error.
+pub async fn serve(addr: &str, storage: SessionStorage) -> Result<()> {
+    let addr: SocketAddr = addr.parse()?;
+    let make_service = make_service_fn(move |_| {
+        let storage = storage.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let storage = storage.clone();
+                async move { Ok::<_, Infallible>(handle(request, storage).await) }
+            }))
+        }
+    });
+
+    tracing::info!("Serve metrics on http://{}/metrics", addr);
+    hyper::Server::try_bind(&addr)?.serve(make_service).await?;
+    Ok(())
+}
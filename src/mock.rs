@@ -0,0 +1,247 @@
+//! Stand-in kite-server, to test the agent end to end without the real one.
+//!
+//! It performs the same handshake as the server, and then talks to the agent in frames built as
+//! JSON values, so only self-describing codecs (JSON and MessagePack) are supported. Frames can be
+//! sent one by one with `MockConnection`, or through `MockClient`, a multiplex client tagging
+//! requests the same way as the server.
+
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use serde_json::{json, Value};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tower::multiplex::{self, MultiplexTransport};
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+use tower::{Service, ServiceExt};
+
+use crate::agent::auth;
+use crate::agent::codec::Codec;
+use crate::agent::frame::{read_frame, write_frame};
+use crate::agent::handshake::{Challenge, ChallengeResponse, Hello, HelloAck, PROTOCOL_VERSION};
+use crate::agent::{Tagged, Tagger};
+use crate::error::Result;
+
+pub struct MockServer {
+    listener: TcpListener,
+    secret: String,
+    codec: Codec,
+}
+
+/// An agent connected to the mock server.
+pub struct MockConnection {
+    inner: Framed<TcpStream, LengthDelimitedCodec>,
+    codec: Codec,
+    /// `Hello` sent by the agent.
+    pub hello: Hello,
+}
+
+/// Build a request frame.
+pub fn request_frame(tag: u32, payload: Value) -> Value {
+    json!({
+        "Request": {
+            "v": { "payload": payload, "deadline": null, "stream": false, "no_cache": false },
+            "tag": tag,
+        }
+    })
+}
+
+impl MockServer {
+    pub async fn bind(addr: &str, secret: &str, codec: Codec) -> Result<Self> {
+        if codec == Codec::Bincode {
+            anyhow::bail!("Mock server does not support bincode.");
+        }
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            secret: secret.to_string(),
+            codec,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Accept an agent and finish the handshake as server.
+    pub async fn accept(&self) -> Result<MockConnection> {
+        let (mut stream, _) = self.listener.accept().await?;
+
+        let hello: Hello = read_frame(&mut stream).await?;
+        let nonce = auth::generate_nonce();
+        let challenge = Challenge {
+            proof: auth::server_proof(&self.secret, &hello.nonce, &nonce),
+            nonce,
+        };
+        write_frame(&mut stream, &challenge).await?;
+
+        let response: ChallengeResponse = read_frame(&mut stream).await?;
+        let accepted =
+            auth::verify_agent_proof(&self.secret, &hello.nonce, &challenge.nonce, &response.proof);
+        let ack = HelloAck {
+            protocol_version: PROTOCOL_VERSION,
+            accepted,
+            message: String::new(),
+            codec: Some(self.codec),
+        };
+        write_frame(&mut stream, &ack).await?;
+
+        if !accepted {
+            anyhow::bail!("Agent proof mismatch.");
+        }
+        Ok(MockConnection {
+            inner: Framed::new(stream, LengthDelimitedCodec::new()),
+            codec: self.codec,
+            hello,
+        })
+    }
+}
+
+impl MockConnection {
+    pub async fn send(&mut self, frame: &Value) -> Result<()> {
+        let content = self.codec.encode(frame)?;
+
+        self.inner.send(Bytes::from(content)).await?;
+        Ok(())
+    }
+
+    /// Send bytes as a frame without encoding, such as a malformed one.
+    pub async fn send_raw(&mut self, content: &[u8]) -> Result<()> {
+        self.inner.send(Bytes::copy_from_slice(content)).await?;
+        Ok(())
+    }
+
+    pub async fn request(&mut self, tag: u32, payload: Value) -> Result<()> {
+        self.send(&request_frame(tag, payload)).await
+    }
+
+    pub async fn cancel(&mut self, tag: u32) -> Result<()> {
+        self.send(&json!({ "Cancel": tag })).await
+    }
+
+    /// Receive the next frame. Heartbeat is answered and skipped, and event is acknowledged.
+    /// Return `None` if the agent closed the connection.
+    pub async fn recv(&mut self) -> Result<Option<Value>> {
+        while let Some(content) = self.inner.next().await {
+            let frame: Value = self.codec.decode(&content?)?;
+
+            if frame == "Heartbeat" {
+                self.send(&json!("Heartbeat")).await?;
+                continue;
+            }
+            if let Some(id) = frame.pointer("/Event/id") {
+                self.send(&json!({ "EventAck": id })).await?;
+            }
+            return Ok(Some(frame));
+        }
+        Ok(None)
+    }
+
+    /// Receive the next response, skipping other frames. Return its tag and payload.
+    pub async fn recv_response(&mut self) -> Result<(u32, Value)> {
+        loop {
+            let frame = self
+                .recv()
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Connection closed by agent."))?;
+
+            if let Some(response) = frame.get("Response") {
+                let response: Tagged<Value> = serde_json::from_value(response.clone())?;
+                return Ok((response.tag, response.v["payload"].clone()));
+            }
+        }
+    }
+
+    /// Turn into a multiplex client.
+    pub fn into_client(self) -> MockClient {
+        let transport = MultiplexTransport::new(ClientTransport(self), Tagger::default());
+        MockClient(multiplex::Client::new(transport))
+    }
+}
+
+/// Adapter between the connection and multiplex client. Only responses are passed to the client.
+struct ClientTransport(MockConnection);
+
+impl ClientTransport {
+    fn decode(&self, content: &[u8]) -> io::Result<Value> {
+        self.0
+            .codec
+            .decode(content)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+}
+
+impl Sink<Tagged<Value>> for ClientTransport {
+    type Error = io::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Sink::<Bytes>::poll_ready(Pin::new(&mut self.0.inner), cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Tagged<Value>) -> io::Result<()> {
+        let content = self
+            .0
+            .codec
+            .encode(&request_frame(item.tag, item.v))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+        Pin::new(&mut self.0.inner).start_send(Bytes::from(content))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Sink::<Bytes>::poll_flush(Pin::new(&mut self.0.inner), cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Sink::<Bytes>::poll_close(Pin::new(&mut self.0.inner), cx)
+    }
+}
+
+impl Stream for ClientTransport {
+    type Item = io::Result<Tagged<Value>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let content = match futures::ready!(Pin::new(&mut self.0.inner).poll_next(cx)) {
+                Some(Ok(content)) => content,
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            };
+            let frame = self.decode(&content)?;
+
+            // Link frames are not answered here, so keep heartbeat timeout of agent long enough.
+            if let Some(response) = frame.get("Response") {
+                let response = serde_json::from_value(response.clone())
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()));
+                return Poll::Ready(Some(response));
+            }
+        }
+    }
+}
+
+type ClientError = tokio_tower::Error<MultiplexTransport<ClientTransport, Tagger>, Tagged<Value>>;
+
+/// Multiplex client sending requests with tags assigned by `Tagger`.
+pub struct MockClient(
+    multiplex::Client<MultiplexTransport<ClientTransport, Tagger>, ClientError, Tagged<Value>>,
+);
+
+impl MockClient {
+    /// Send requests at the same time, and return their responses in the same order.
+    pub async fn call_all(&mut self, payloads: Vec<Value>) -> Result<Vec<Value>> {
+        let mut responses = Vec::new();
+
+        for payload in payloads {
+            let client = self.0.ready().await.map_err(|e| anyhow::anyhow!("{:?}", e))?;
+            responses.push(client.call(Tagged::from(payload)));
+        }
+        let mut results = Vec::new();
+        for response in futures::future::join_all(responses).await {
+            let response = response.map_err(|e| anyhow::anyhow!("{:?}", e))?;
+            results.push(response.v["payload"].clone());
+        }
+        Ok(results)
+    }
+}
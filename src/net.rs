@@ -1,3 +1,4 @@
+pub use availability::{test_network_connectivity, NetworkConnectivity};
 pub use client::{parse_domain, UserClient};
 pub use session::AccountCookies;
 pub use session::{Session, SessionStorage};
@@ -5,5 +6,12 @@ pub use session::{Session, SessionStorage};
 pub mod auth;
 mod availability;
 pub(crate) mod client;
+pub mod dns;
+pub mod proxy;
 mod session;
 mod user_agent;
+
+/// Build the HTTP client used to access campus systems, with proxies in config.
+pub fn build_http_client() -> crate::error::Result<reqwest::Client> {
+    proxy::build_client(&crate::config::CONFIG)
+}
@@ -1,19 +1,21 @@
 use reqwest::{Request, StatusCode};
 
+use crate::config::CONFIG;
 use crate::error::Result;
 use crate::make_parameter;
+use crate::metrics;
 use crate::service::ActionError;
 
 use super::client::is_request_redirecting;
 use super::{Session, UserClient};
 
-/// Login page.
-#[allow(dead_code)]
-const LOGIN_URL: &str = "https://authserver.sit.edu.cn/authserver/login";
-#[allow(dead_code)]
-const NEED_CAPTCHA_URL: &str = "https://authserver.sit.edu.cn/authserver/needCaptcha.html";
-#[allow(dead_code)]
-const CAPTCHA_URL: &str = "https://authserver.sit.edu.cn/authserver/captcha.html";
+lazy_static! {
+    /// Login page.
+    static ref LOGIN_URL: String = format!("{}/authserver/login", CONFIG.endpoints.authserver);
+    static ref NEED_CAPTCHA_URL: String =
+        format!("{}/authserver/needCaptcha.html", CONFIG.endpoints.authserver);
+    static ref CAPTCHA_URL: String = format!("{}/authserver/captcha.html", CONFIG.endpoints.authserver);
+}
 
 /// Search in text by regex, and return the first group.
 #[macro_export]
@@ -28,7 +30,7 @@ macro_rules! regex_find {
 pub async fn check_need_captcha(client: &mut UserClient, account: &str) -> Result<bool> {
     let url = format!(
         "{}?{}",
-        NEED_CAPTCHA_URL,
+        *NEED_CAPTCHA_URL,
         make_parameter!(
             "username" => account,
             "pwdEncrypt2" => "pwdEncryptSalt")
@@ -37,7 +39,7 @@ pub async fn check_need_captcha(client: &mut UserClient, account: &str) -> Resul
     let check_result = client.send(request).await?;
 
     let result_text = check_result.text().await?;
-    println!("result_text = {}", result_text);
+    tracing::debug!(%result_text, "Captcha check.");
     Ok(result_text == "true")
 }
 
@@ -113,11 +115,12 @@ pub async fn portal_login(
                 if captcha.len() == 4 {
                     break;
                 }
+                metrics::CAPTCHA_RETRIES.inc();
             }
         }
         let login_request = client
             .raw_client
-            .post(LOGIN_URL)
+            .post(LOGIN_URL.as_str())
             .form(&[
                 ("username", user_name),
                 (
@@ -140,6 +143,7 @@ pub async fn portal_login(
         let response = client.send(login_request).await?;
         // Login successfully.
         if is_request_redirecting(response.status()) {
+            metrics::LOGIN_ATTEMPTS.with_label_values(&["success"]).inc();
             return Ok(client.session);
         }
         // Password error
@@ -147,12 +151,16 @@ pub async fn portal_login(
             let response_text = response.text().await?;
             if response_text.contains("您提供的用户名或者密码有误") {
                 // If successfully authenticated or password wrong, break.
+                metrics::LOGIN_ATTEMPTS
+                    .with_label_values(&["wrong_password"])
+                    .inc();
                 return Err(ActionError::LoginFailed.into());
             } else {
                 // Else, captcha wrong, or other error, make a captcha challenge again.
             }
         }
 
+        metrics::LOGIN_ATTEMPTS.with_label_values(&["retry"]).inc();
         try_count -= 1;
     }
     Err(ActionError::Unknown.into())
@@ -41,7 +41,7 @@ fn get_test_page() -> &'static NetworkTestPage {
 }
 
 /// Network connectivity status.
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize)]
 pub enum NetworkConnectivity {
     /// Can be used normally.
     Connected,
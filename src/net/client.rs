@@ -1,7 +1,10 @@
 use reqwest::header::HeaderValue;
 use reqwest::{Client, Response, StatusCode};
 
+use crate::agent::event::{self, AgentEvent};
 use crate::error::Result;
+use crate::metrics;
+use crate::service::ActionError;
 
 use super::Session;
 
@@ -54,6 +57,11 @@ impl UserClient {
         self.response_hook = hook;
     }
 
+    #[tracing::instrument(
+        name = "send",
+        skip_all,
+        fields(method = %request.method(), host = request.url().host_str().unwrap_or_default(), path = request.url().path())
+    )]
     pub async fn send(&mut self, request: reqwest::Request) -> Result<Response> {
         let mut complete_url;
         let mut request = request;
@@ -76,7 +84,9 @@ impl UserClient {
                 hook(&mut request);
             }
             /* Execute request */
+            metrics::UPSTREAM_REQUESTS.with_label_values(&[&domain]).inc();
             let mut response = self.raw_client.execute(request).await?;
+            tracing::debug!(path = response.url().path(), status = %response.status(), "Response received.");
             /* Store new cookies to session */
             self.session.sync_cookies(&domain, response.cookies());
             /* Call response hook */
@@ -97,8 +107,19 @@ impl UserClient {
         /* Unreachable. */
     }
 
+    /// Login with the session. Server is told the session expired only when the password is
+    /// rejected, not on network errors or unavailable campus systems.
     pub async fn login_with_session(&mut self) -> Result<()> {
-        self.session.login(&self.raw_client).await
+        let result = self.session.login(&self.raw_client).await;
+        let rejected =
+            matches!(&result, Err(e) if matches!(e.downcast_ref(), Some(ActionError::LoginFailed)));
+
+        if rejected {
+            event::emit(AgentEvent::SessionExpired {
+                account: self.session.account.clone(),
+            });
+        }
+        result
     }
 }
 
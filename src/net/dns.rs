@@ -0,0 +1,107 @@
+//! Name resolution by `[dns]`: static host addresses, and DNS servers such as the intranet DNS for
+//! agents deployed off-campus.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use hickory_resolver::config::{
+    NameServerConfig, Protocol, ResolverConfig, ResolverOpts, ServerOrderingStrategy,
+};
+use hickory_resolver::TokioAsyncResolver;
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::ClientBuilder;
+
+use crate::config::DnsConfig;
+use crate::error::{AgentError, Result};
+
+const DNS_PORT: u16 = 53;
+
+/// Resolver querying the configured DNS servers in order, over UDP and TCP for truncated answers.
+#[derive(Clone)]
+pub struct Resolver(TokioAsyncResolver);
+
+/// Parse a DNS server address, on port 53 if not given.
+fn parse_server(server: &str) -> Result<SocketAddr> {
+    server
+        .parse::<SocketAddr>()
+        .or_else(|_| server.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, DNS_PORT)))
+        .map_err(|_| AgentError::Config(format!("Invalid DNS server {}", server)).into())
+}
+
+impl Resolver {
+    pub fn new(config: &DnsConfig) -> Result<Self> {
+        let mut resolver_config = ResolverConfig::new();
+        for server in &config.servers {
+            let addr = parse_server(server)?;
+
+            resolver_config.add_name_server(NameServerConfig::new(addr, Protocol::Udp));
+            resolver_config.add_name_server(NameServerConfig::new(addr, Protocol::Tcp));
+        }
+        let mut options = ResolverOpts::default();
+        options.timeout = Duration::from_secs(config.timeout);
+        options.server_ordering_strategy = ServerOrderingStrategy::UserProvidedOrder;
+
+        Ok(Self(TokioAsyncResolver::tokio(resolver_config, options)))
+    }
+}
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.0.clone();
+
+        Box::pin(async move {
+            let lookup = resolver.lookup_ip(name.as_str()).await?;
+            // Port is set by the connector.
+            let addrs = lookup.into_iter().map(|ip| SocketAddr::new(ip, 0));
+            Ok(Box::new(addrs) as Addrs)
+        })
+    }
+}
+
+/// Resolve hosts of the client by `[dns]`. Static hosts take precedence over DNS servers, and
+/// the system resolver is used if no server is configured.
+pub fn configure(mut builder: ClientBuilder, config: &DnsConfig) -> Result<ClientBuilder> {
+    for (host, ip) in &config.hosts {
+        let ip: IpAddr = ip
+            .parse()
+            .map_err(|_| AgentError::Config(format!("Invalid address {} of {}", ip, host)))?;
+        builder = builder.resolve(&host.to_lowercase(), SocketAddr::new(ip, 0));
+    }
+    if !config.servers.is_empty() {
+        builder = builder.dns_resolver(Arc::new(Resolver::new(config)?));
+    }
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{configure, parse_server};
+    use crate::config::DnsConfig;
+
+    #[test]
+    fn test_parse_server() {
+        assert_eq!(parse_server("10.0.0.1").unwrap().port(), 53);
+        assert_eq!(parse_server("10.0.0.1:5353").unwrap().port(), 5353);
+        assert!(parse_server("dns.sit.edu.cn").is_err());
+    }
+
+    #[test]
+    fn test_configure() {
+        let config = |servers: &[&str], hosts: &[(&str, &str)]| DnsConfig {
+            servers: servers.iter().map(ToString::to_string).collect(),
+            hosts: hosts
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        };
+        let builder = reqwest::Client::builder;
+
+        // Checked on loading config, out of any runtime.
+        assert!(configure(builder(), &config(&["10.0.0.1", "10.0.0.2:5353"], &[])).is_ok());
+        assert!(configure(builder(), &config(&[], &[("jwxt.sit.edu.cn", "127.0.0.1")])).is_ok());
+        assert!(configure(builder(), &config(&[], &[("jwxt.sit.edu.cn", "localhost")])).is_err());
+    }
+}
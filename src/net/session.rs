@@ -107,6 +107,16 @@ impl SessionStorage {
     pub fn len(&self) -> usize {
         self.db.len()
     }
+
+    /// Generate a unique id, which is never reused even after restart.
+    pub fn generate_id(&self) -> Result<u64> {
+        Ok(self.db.generate_id()?)
+    }
+
+    /// Open another tree in the database, for data other than sessions.
+    pub fn open_tree(&self, name: &str) -> Result<sled::Tree> {
+        Ok(self.db.open_tree(name)?)
+    }
 }
 
 // Note: You should not implement Default for SessionStorage. If you write code like this:
@@ -4,24 +4,25 @@ use serde_json::Value;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Major {
-    #[serde(skip_deserializing)]
+    // Fields accept names of both jwxt and our own, so that cached majors can be read back.
+    #[serde(default)]
     entrance_year: i32,
-    #[serde(skip_serializing, rename(deserialize = "njdm"))]
+    #[serde(default, skip_serializing, rename(deserialize = "njdm"))]
     /// 入学年份
     _entrance_year: String,
-    #[serde(rename(deserialize = "zyh"))]
+    #[serde(alias = "zyh")]
     /// 专业代码
     id: String,
-    #[serde(rename(deserialize = "zymc"))]
+    #[serde(alias = "zymc")]
     /// 专业名称
     name: String,
-    #[serde(rename(deserialize = "zyh_id"))]
+    #[serde(alias = "zyh_id")]
     /// 专业内部标识
     inner_id: String,
-    #[serde(rename(deserialize = "zyfx_id"))]
+    #[serde(alias = "zyfx_id")]
     /// 专业方向内部表示
     direction_id: String,
-    #[serde(rename(deserialize = "zyfxmc"))]
+    #[serde(alias = "zyfxmc")]
     /// 专业方向
     direction: String,
 }
@@ -164,6 +165,13 @@ fn test_parse_major_list_page() {
 
     let parsed_major_list = parse_major_list_page(page);
     println!("{:#?}", parsed_major_list);
+
+    // Majors read back from cache are the same.
+    let majors = parsed_major_list.unwrap();
+    let cached: Vec<Major> = serde_json::from_str(&serde_json::to_string(&majors).unwrap()).unwrap();
+    assert_eq!(cached[0].entrance_year, 2018);
+    assert_eq!(cached[0].name, majors[0].name);
+    assert_eq!(cached[1].direction_id, "2018B210000");
 }
 
 #[test]
@@ -25,7 +25,7 @@ lazy_static! {
 }
 
 /// Activity link, used for list recent activities.
-#[derive(serde::Serialize, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct ActivityDetail {
     /// Activity id
     pub id: i32,
@@ -57,7 +57,7 @@ pub struct ActivityDetail {
     pub images: Vec<ScImages>,
 }
 
-#[derive(serde::Serialize, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct ScImages {
     pub new_name: String,
     pub old_name: String,
@@ -1,13 +1,13 @@
 use chrono::NaiveDateTime;
 use regex::Regex;
 use scraper::{Html, Selector};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::error::Result;
 use crate::parser::Parse;
 
 /// Activity link, used for list recent activities.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Activity {
     pub id: i32,
     pub category: i32,
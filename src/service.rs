@@ -1,11 +1,15 @@
 use serde::{Deserialize, Serialize};
+use strum::VariantNames;
+use strum_macros::{EnumIter, EnumVariantNames, IntoStaticStr};
 
 use auth::{PortalAuthRequest, PortalAuthResponse};
+pub use batch::BatchSessions;
 pub use edu::{
     ClassRequest, CourseRequest, ExamArrangement, ExamArrangeRequest, MajorRequest, ProfileRequest,
     ScoreDetailRequest, ScoreRequest, TimeTableRequest,
 };
 pub use error::{ActionError, ErrorResponse};
+pub use job::JobStatus;
 pub use library::{BookHoldingRequest, SearchLibraryRequest, SearchWay, SortOrder, SortWay};
 use report::AgentInfo;
 pub use report::AgentInfoRequest;
@@ -22,15 +26,18 @@ use crate::parser::{
 use crate::service::expense::ExpenseRequest;
 
 mod auth;
+mod batch;
+pub mod cache;
 mod edu;
 mod error;
 mod expense;
+pub mod job;
 mod library;
 pub mod report;
 mod sc;
 
-/// Response payload
-#[derive(Debug, Deserialize)]
+/// Request payload
+#[derive(Debug, Serialize, Deserialize, EnumVariantNames, IntoStaticStr)]
 pub enum RequestPayload {
     None,
     Ping(String),
@@ -52,6 +59,12 @@ pub enum RequestPayload {
     BookHoldingInfo(BookHoldingRequest),
     CardExpense(ExpenseRequest),
     ExamArrange(ExamArrangeRequest),
+    /// Requests processed concurrently, sharing sessions of the same account.
+    Batch(Vec<RequestPayload>),
+    /// Request saved as a job and retried in background until done, see `job` module.
+    Eventually(Box<RequestPayload>),
+    /// Query a job by its id.
+    JobStatus(u64),
 }
 
 /// Response payload
@@ -77,6 +90,63 @@ pub enum ResponsePayload {
     BookHoldingInfo(HoldingPreviews),
     CardExpense(ExpensePage),
     ExamArrange(Vec<ExamArrangement>),
+    /// Results of a batch, in the order of its sub-requests.
+    Batch(Vec<ResponseResult>),
+    /// Id of the job saved for an `Eventually` request.
+    JobAccepted(u64),
+    Job(JobStatus),
+    /// End of a streamed response, with the count of chunks sent.
+    EndOfStream(u32),
+}
+
+/// Part of a streamed response.
+#[derive(Debug, Serialize)]
+pub enum ResponseChunk {
+    /// A piece of result, in the same type as the non-streamed response.
+    Payload(ResponsePayload),
+    /// A piece of binary data, such as an image.
+    Blob {
+        /// Name to identify the data in the response.
+        name: String,
+        /// Offset of this piece.
+        offset: u64,
+        /// Total size of the data.
+        total: u64,
+        data: Vec<u8>,
+    },
+}
+
+/// Campus systems which requests are sent to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, EnumIter)]
+pub enum CampusSystem {
+    /// authserver.sit.edu.cn
+    AuthServer,
+    /// jwxt.sit.edu.cn
+    Jwxt,
+    /// sc.sit.edu.cn
+    SecondClass,
+    /// card.sit.edu.cn
+    Card,
+    /// Library OPAC.
+    Library,
+}
+
+impl CampusSystem {
+    /// Name in config, such as in `[limit]` and `[proxy.routes]`.
+    pub fn key(&self) -> &'static str {
+        match self {
+            CampusSystem::AuthServer => "authserver",
+            CampusSystem::Jwxt => "jwxt",
+            CampusSystem::SecondClass => "sc",
+            CampusSystem::Card => "card",
+            CampusSystem::Library => "library",
+        }
+    }
+
+    /// Home page of the system in `[endpoints]`, used to check whether it's reachable.
+    pub fn home(&self) -> &'static str {
+        crate::config::CONFIG.endpoints.of(*self)
+    }
 }
 
 #[async_trait::async_trait]
@@ -100,6 +170,74 @@ macro_rules! make_parameter {
 pub type ResponseResult = std::result::Result<ResponsePayload, ErrorResponse>;
 
 impl RequestPayload {
+    /// Name of the request kind, the same as the variant name.
+    pub fn kind(&self) -> &'static str {
+        self.into()
+    }
+
+    /// Find the request kind by name, ignoring case, underscores and hyphens. e.g. "score_detail"
+    /// for `ScoreDetail`.
+    pub fn kind_by_name(name: &str) -> Option<&'static str> {
+        let name = name.replace(['_', '-'], "");
+
+        Self::VARIANTS
+            .iter()
+            .copied()
+            .find(|kind| kind.eq_ignore_ascii_case(&name))
+    }
+
+    /// Build a request of the kind with JSON parameter.
+    pub fn from_json(kind: &str, parameter: serde_json::Value) -> serde_json::Result<Self> {
+        serde_json::from_value(serde_json::json!({ kind: parameter }))
+    }
+
+    /// Account the request works on, if any.
+    pub fn account(&self) -> Option<&str> {
+        let account = match self {
+            RequestPayload::PortalAuth(r) => &r.account,
+            RequestPayload::ScMyScore(r) => &r.account,
+            RequestPayload::ScMyActivity(r) => &r.account,
+            RequestPayload::ScActivityJoin(r) => &r.account,
+            RequestPayload::MajorList(r) => &r.account,
+            RequestPayload::TimeTable(r) => &r.account,
+            RequestPayload::Score(r) => &r.account,
+            RequestPayload::ScoreDetail(r) => &r.account,
+            RequestPayload::CardExpense(r) => &r.account,
+            RequestPayload::ExamArrange(r) => &r.account,
+            RequestPayload::Eventually(r) => return r.account(),
+            _ => return None,
+        };
+        Some(account)
+    }
+
+    /// Campus system the request mainly works on. `None` for requests handled by agent itself.
+    pub fn target(&self) -> Option<CampusSystem> {
+        match self {
+            // Sub-requests of a batch, and jobs, take slots by themselves.
+            RequestPayload::None
+            | RequestPayload::Ping(_)
+            | RequestPayload::AgentInfo(_)
+            | RequestPayload::Batch(_)
+            | RequestPayload::Eventually(_)
+            | RequestPayload::JobStatus(_) => None,
+            RequestPayload::PortalAuth(_) => Some(CampusSystem::AuthServer),
+            RequestPayload::ActivityList(_)
+            | RequestPayload::ActivityDetail(_)
+            | RequestPayload::ScMyScore(_)
+            | RequestPayload::ScMyActivity(_)
+            | RequestPayload::ScActivityJoin(_) => Some(CampusSystem::SecondClass),
+            RequestPayload::MajorList(_)
+            | RequestPayload::TimeTable(_)
+            | RequestPayload::Score(_)
+            | RequestPayload::ScoreDetail(_)
+            | RequestPayload::ExamArrange(_) => Some(CampusSystem::Jwxt),
+            RequestPayload::SearchLibrary(_) | RequestPayload::BookHoldingInfo(_) => {
+                Some(CampusSystem::Library)
+            }
+            RequestPayload::CardExpense(_) => Some(CampusSystem::Card),
+        }
+    }
+
     pub(crate) async fn dispatch(self, data: SharedData) -> ResponseResult {
         match self {
             RequestPayload::None => Ok(ResponsePayload::None),
@@ -122,6 +260,9 @@ impl RequestPayload {
             RequestPayload::BookHoldingInfo(r) => r.process(data).await,
             RequestPayload::CardExpense(r) => r.process(data).await,
             RequestPayload::ExamArrange(r) => r.process(data).await,
+            RequestPayload::Batch(r) => batch::process(r, data).await,
+            RequestPayload::Eventually(r) => job::submit(*r, &data),
+            RequestPayload::JobStatus(id) => job::status(id, &data),
         }
     }
 }
@@ -4,9 +4,9 @@ use crate::service::{ResponsePayload, ResponseResult};
 
 use super::DoRequest;
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct PortalAuthRequest {
-    account: String,
+    pub account: String,
     credential: String,
 }
 
@@ -0,0 +1,89 @@
+//! Batch of requests, such as those to refresh a dashboard, processed concurrently.
+//!
+//! Sub-requests of the same account share one session on each campus system, so the session is
+//! looked up and activated only once in a batch.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::OnceCell;
+
+use crate::agent::{self, SharedData};
+use crate::error::Result;
+use crate::net::client::default_response_hook;
+use crate::net::{Session, UserClient};
+
+use super::{ActionError, CampusSystem, RequestPayload, ResponsePayload, ResponseResult};
+
+/// Max sub-requests in a batch.
+const MAX_BATCH_SIZE: usize = 32;
+
+type Activation<'a> = Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+
+/// Session of an account on a campus system, set once it's active.
+type SessionSlot = Arc<OnceCell<Session>>;
+
+/// Active sessions shared by sub-requests of a batch.
+#[derive(Debug, Clone, Default)]
+pub struct BatchSessions(Arc<Mutex<HashMap<(String, CampusSystem), SessionSlot>>>);
+
+impl BatchSessions {
+    fn slot(&self, account: &str, system: CampusSystem) -> SessionSlot {
+        let mut sessions = self.0.lock().unwrap();
+
+        sessions.entry((account.to_string(), system)).or_default().clone()
+    }
+}
+
+/// Create a client of the account, and make its session active on the system by `activate`. In a
+/// batch, the active session is reused by later sub-requests of the account.
+pub(super) async fn active_client<F>(
+    data: &SharedData,
+    account: &str,
+    password: &str,
+    system: CampusSystem,
+    activate: F,
+) -> Result<UserClient>
+where
+    F: for<'a> FnOnce(&'a mut UserClient) -> Activation<'a>,
+{
+    let new_client = |session| {
+        let mut client = UserClient::new(session, &data.client);
+        client.set_response_hook(Some(default_response_hook));
+        client
+    };
+    let activate_new = || async {
+        let mut client = new_client(data.session_store.query_or(account, password)?);
+        activate(&mut client).await?;
+        Ok::<_, anyhow::Error>(client)
+    };
+
+    match &data.batch {
+        Some(sessions) => {
+            let slot = sessions.slot(account, system);
+            let session = slot
+                .get_or_try_init(|| async { activate_new().await.map(|client| client.session) })
+                .await?;
+            Ok(new_client(session.clone()))
+        }
+        None => activate_new().await,
+    }
+}
+
+/// Process sub-requests concurrently. Each of them has its own result.
+pub(super) async fn process(requests: Vec<RequestPayload>, mut data: SharedData) -> ResponseResult {
+    // Nested batch is not allowed.
+    if requests.len() > MAX_BATCH_SIZE || data.batch.is_some() {
+        return Err(ActionError::BadParameter.into());
+    }
+    // Sub-requests are not streamed.
+    data.stream = None;
+    data.batch = Some(BatchSessions::default());
+
+    let results = requests
+        .into_iter()
+        .map(|request| agent::dispatch_limited(request, data.clone()));
+    Ok(ResponsePayload::Batch(futures::future::join_all(results).await))
+}
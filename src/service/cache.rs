@@ -0,0 +1,165 @@
+//! Cache of responses with public data, such as library search results, in the sled database.
+//!
+//! TTL is configured by request kind in `[cache]`. Entries are keyed by the request kind and the
+//! request fields in JSON, and stored in JSON since some parsed types skip fields when serialized.
+//! Expired entries are removed on lookup, and by `sweep` periodically since keys such as search
+//! keywords may never be looked up again.
+
+use std::future::Future;
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::agent::SharedData;
+use crate::config::CONFIG;
+use crate::error::Result;
+use crate::metrics;
+
+/// Name of sled tree storing the cache.
+const CACHE_TREE: &str = "cache";
+/// Interval to remove expired entries.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(600);
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry<T> {
+    /// Expiry time in unix timestamp.
+    expire_at: i64,
+    response: T,
+}
+
+/// Expiry time of an entry, decoded without the response.
+#[derive(Deserialize)]
+struct Expiry {
+    expire_at: i64,
+}
+
+fn query<T: DeserializeOwned>(tree: &sled::Tree, key: &str) -> Result<Option<T>> {
+    let entry = match tree.get(key)? {
+        Some(value) => serde_json::from_slice::<CacheEntry<T>>(&value).ok(),
+        None => return Ok(None),
+    };
+
+    match entry {
+        Some(entry) if entry.expire_at > Utc::now().timestamp() => Ok(Some(entry.response)),
+        // Remove expired entries, or those saved in an old format.
+        _ => {
+            tree.remove(key)?;
+            Ok(None)
+        }
+    }
+}
+
+/// Get the cached response of the request, or run `fetch` and cache its result. `key` is usually
+/// the request itself, and should not include credentials since the response is public.
+///
+/// The cache is skipped, and refreshed, if the request asks for `no_cache`.
+pub(super) async fn get_or_fetch<K, T, F>(data: &SharedData, kind: &str, key: &K, fetch: F) -> Result<T>
+where
+    K: Serialize,
+    T: Serialize + DeserializeOwned,
+    F: Future<Output = Result<T>>,
+{
+    let ttl = match CONFIG.cache.ttl(kind) {
+        Some(ttl) => ttl,
+        None => return fetch.await,
+    };
+    let tree = data.session_store.open_tree(CACHE_TREE)?;
+    let key = format!("{}:{}", kind, serde_json::to_string(key)?);
+
+    if !data.no_cache {
+        if let Some(response) = query(&tree, &key)? {
+            metrics::CACHE_LOOKUPS.with_label_values(&[kind, "hit"]).inc();
+            return Ok(response);
+        }
+    }
+    metrics::CACHE_LOOKUPS.with_label_values(&[kind, "miss"]).inc();
+
+    let response = fetch.await?;
+    let entry = CacheEntry {
+        expire_at: Utc::now().timestamp() + ttl.as_secs() as i64,
+        response,
+    };
+    tree.insert(key, serde_json::to_vec(&entry)?)?;
+    Ok(entry.response)
+}
+
+/// Remove entries expired before `now`, or saved in an old format. Return the count removed.
+fn purge(tree: &sled::Tree, now: i64) -> Result<usize> {
+    let mut removed = 0;
+
+    for item in tree.iter() {
+        let (key, value) = item?;
+        match serde_json::from_slice::<Expiry>(&value) {
+            Ok(entry) if entry.expire_at > now => {}
+            _ => {
+                tree.remove(key)?;
+                removed += 1;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// Remove expired entries periodically until the agent exits.
+pub async fn sweep(data: SharedData) -> Result<()> {
+    let tree = data.session_store.open_tree(CACHE_TREE)?;
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        match purge(&tree, Utc::now().timestamp()) {
+            Ok(0) => {}
+            Ok(removed) => info!(removed, "Expired cache removed."),
+            Err(e) => warn!("Failed to remove expired cache: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+
+    use super::{purge, query, CacheEntry};
+
+    #[test]
+    fn test_query_expiry() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let tree = db.open_tree("cache").unwrap();
+        let now = Utc::now().timestamp();
+
+        for (key, expire_at) in &[("fresh", now + 60), ("expired", now - 1)] {
+            let entry = CacheEntry {
+                expire_at: *expire_at,
+                response: key.to_string(),
+            };
+            tree.insert(key, serde_json::to_vec(&entry).unwrap()).unwrap();
+        }
+        assert_eq!(query::<String>(&tree, "fresh").unwrap().as_deref(), Some("fresh"));
+        assert_eq!(query::<String>(&tree, "expired").unwrap(), None);
+        assert!(!tree.contains_key("expired").unwrap());
+        // Entry of another type is dropped too.
+        assert_eq!(query::<u32>(&tree, "fresh").unwrap(), None);
+    }
+
+    #[test]
+    fn test_purge() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let tree = db.open_tree("cache").unwrap();
+
+        for (key, expire_at) in &[("fresh", 200), ("expired", 100)] {
+            let entry = CacheEntry {
+                expire_at: *expire_at,
+                response: vec![1, 2, 3],
+            };
+            tree.insert(key, serde_json::to_vec(&entry).unwrap()).unwrap();
+        }
+        tree.insert("old format", b"[]".as_ref()).unwrap();
+
+        assert_eq!(purge(&tree, 150).unwrap(), 2);
+        assert!(tree.contains_key("fresh").unwrap());
+        assert_eq!(tree.len(), 1);
+    }
+}
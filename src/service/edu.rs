@@ -2,8 +2,10 @@ pub use env::{ClassRequest, CourseRequest, MajorRequest};
 pub use exam::{ExamArrangement, ExamArrangeRequest};
 pub use user::{ProfileRequest, ScoreDetailRequest, ScoreRequest, TimeTableRequest};
 
+use crate::agent::SharedData;
 use crate::error::Result;
 use crate::net::UserClient;
+use crate::service::{batch, CampusSystem};
 
 mod auth;
 mod env;
@@ -12,58 +14,77 @@ mod user;
 
 /// URL probably used in the module.
 mod url {
-    use const_format::concatcp;
+    use crate::config::CONFIG;
 
-    /// Server address for 正方教务系统
-    pub const HOME: &str = "http://jwxt.sit.edu.cn";
+    lazy_static! {
+        /// Server address for 正方教务系统
+        pub static ref HOME: &'static str = &CONFIG.endpoints.jwxt;
 
-    /* Login related */
+        /* Login related */
 
-    pub const LOGIN: &str = concatcp!(HOME, "/jwglxt/xtgl/login_slogin.html");
-    pub const RSA_PUBLIC_KEY: &str = concatcp!(HOME, "/jwglxt/xtgl/login_getPublicKey.html");
-    pub const SSO_EDU_REDIRECT: &str = "https://authserver.sit.edu.cn/authserver/login?service=http%3A%2F%2Fjwxt.sit.edu.cn%2Fsso%2Fjziotlogin";
+        pub static ref LOGIN: String = format!("{}/jwglxt/xtgl/login_slogin.html", *HOME);
+        pub static ref RSA_PUBLIC_KEY: String = format!("{}/jwglxt/xtgl/login_getPublicKey.html", *HOME);
+        pub static ref SSO_EDU_REDIRECT: String = format!(
+            "{}/authserver/login?service={}",
+            CONFIG.endpoints.authserver,
+            urlencoding::encode(&format!("{}/sso/jziotlogin", *HOME))
+        );
 
-    /* function related */
+        /* function related */
 
-    /// Score list page
-    pub const SCORE_LIST: &str = concatcp!(
-        HOME,
-        "/jwglxt/cjcx/cjcx_cxDgXscj.html?doType=query&gnmkdm=N305005"
-    );
-    /// Score detail page
-    pub const SCORE_DETAIL: &str = concatcp!(HOME, "/jwglxt/cjcx/cjcx_cxCjxqGjh.html?gnmkdm=N305005");
-    /// Time tanle page
-    pub const TIME_TABLE: &str = concatcp!(HOME, "/jwglxt/kbcx/xskbcx_cxXsKb.html?gnmkdm=N253508");
-    /// Personal profile page
-    pub const PROFILE: &str = concatcp!(
-        HOME,
-        "/jwglxt/xsxxxggl/xsgrxxwh_cxXsgrxx.html?gnmkdm=N100801&layout=default"
-    );
-    /// Major list page
-    pub const MAJOR_LIST: &str = concatcp!(HOME, "/jwglxt/xtgl/comm_cxZyfxList.html?gnmkdm=N214505");
-    /// Class list page
-    pub const CLASS_LIST: &str = concatcp!(HOME, "/jwglxt/xtgl/comm_cxBjdmList.html?gnmkdm=N214505");
-    /// Suggested course and time table
-    pub const SUGGESTED_COURSE: &str = concatcp!(HOME, "/jwglxt/kbdy/bjkbdy_cxBjKb.html?gnmkdm=N214505");
+        /// Score list page
+        pub static ref SCORE_LIST: String = format!(
+            "{}/jwglxt/cjcx/cjcx_cxDgXscj.html?doType=query&gnmkdm=N305005",
+            *HOME
+        );
+        /// Score detail page
+        pub static ref SCORE_DETAIL: String =
+            format!("{}/jwglxt/cjcx/cjcx_cxCjxqGjh.html?gnmkdm=N305005", *HOME);
+        /// Time tanle page
+        pub static ref TIME_TABLE: String =
+            format!("{}/jwglxt/kbcx/xskbcx_cxXsKb.html?gnmkdm=N253508", *HOME);
+        /// Personal profile page
+        pub static ref PROFILE: String = format!(
+            "{}/jwglxt/xsxxxggl/xsgrxxwh_cxXsgrxx.html?gnmkdm=N100801&layout=default",
+            *HOME
+        );
+        /// Major list page
+        pub static ref MAJOR_LIST: String =
+            format!("{}/jwglxt/xtgl/comm_cxZyfxList.html?gnmkdm=N214505", *HOME);
+        /// Class list page
+        pub static ref CLASS_LIST: String =
+            format!("{}/jwglxt/xtgl/comm_cxBjdmList.html?gnmkdm=N214505", *HOME);
+        /// Suggested course and time table
+        pub static ref SUGGESTED_COURSE: String =
+            format!("{}/jwglxt/kbdy/bjkbdy_cxBjKb.html?gnmkdm=N214505", *HOME);
 
-    /// Exam arrangement
-    pub const EXAM_ARRANGEMENT: &str = concatcp!(
-        HOME,
-        "/jwglxt/kwgl/kscx_cxXsksxxIndex.html?doType=query&gnmkdm=N358105"
-    );
+        /// Exam arrangement
+        pub static ref EXAM_ARRANGEMENT: String = format!(
+            "{}/jwglxt/kwgl/kscx_cxXsksxxIndex.html?doType=query&gnmkdm=N358105",
+            *HOME
+        );
+    }
 }
 
 async fn make_sure_active(client: &mut UserClient) -> Result<()> {
-    let home_request = client.raw_client.get(url::HOME).build()?;
+    let home_request = client.raw_client.get(*url::HOME).build()?;
     let response = client.send(home_request).await?;
 
-    if response.url().as_str() == url::LOGIN {
+    if response.url().as_str() == *url::LOGIN {
         // The session is already expired, re-login now.
         client.login_with_session().await?;
 
         // Use SSO to Zhengfang system.
-        let request = client.raw_client.get(url::SSO_EDU_REDIRECT).build()?;
+        let request = client.raw_client.get(url::SSO_EDU_REDIRECT.as_str()).build()?;
         let _ = client.send(request).await?;
     }
     Ok(())
 }
+
+/// Client of the account with an active session on jwxt.
+async fn active_client(data: &SharedData, account: &str, password: &str) -> Result<UserClient> {
+    batch::active_client(data, account, password, CampusSystem::Jwxt, |client| {
+        Box::pin(make_sure_active(client))
+    })
+    .await
+}
@@ -38,7 +38,7 @@ pub async fn get_rsa_public_key(client: &mut UserClient) -> anyhow::Result<(Vec<
         exponent: String,
     }
 
-    let res = client.raw_client.get(url::RSA_PUBLIC_KEY).build()?;
+    let res = client.raw_client.get(url::RSA_PUBLIC_KEY.as_str()).build()?;
     let resp = client.send(res).await?;
 
     let public_key = resp.json::<RsaPublicKey>().await?;
@@ -70,7 +70,7 @@ fn parse_err_message(content: &str) -> String {
 pub async fn login(client: &mut UserClient) -> Result<String> {
     client.session.cookies.clear();
 
-    let login = client.raw_client.get(url::HOME).build()?;
+    let login = client.raw_client.get(*url::HOME).build()?;
     let login_page = client.send(login).await?;
 
     let text = login_page.text().await?;
@@ -85,9 +85,13 @@ pub async fn login(client: &mut UserClient) -> Result<String> {
             ("yhm", &client.session.account),
             ("mm", &encrypted_password),
         ];
-        let response_f = client.raw_client.post(url::LOGIN).form(&params).build()?;
+        let response_f = client
+            .raw_client
+            .post(url::LOGIN.as_str())
+            .form(&params)
+            .build()?;
         let final_response = client.send(response_f).await?;
-        return if final_response.url().to_string().starts_with(url::LOGIN) {
+        return if final_response.url().to_string().starts_with(url::LOGIN.as_str()) {
             let text = final_response.text().await?;
             let error = parse_err_message(&text);
 
@@ -1,12 +1,11 @@
 use async_trait::async_trait;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::agent::SharedData;
-use crate::net::client::default_response_hook;
-use crate::net::UserClient;
+use crate::error::Result;
 use crate::parser::*;
-use crate::service::edu::make_sure_active;
-use crate::service::{DoRequest, ResponsePayload, ResponseResult};
+use crate::service::edu::active_client;
+use crate::service::{cache, DoRequest, ResponsePayload, ResponseResult};
 
 use super::url;
 
@@ -36,7 +35,7 @@ pub struct ClassRequest {
 //             ("queryModel.showCount", 10000.to_string()),
 //         ];
 //
-//         let request = client.raw_client.post(url::CLASS_LIST).form(&params).build()?;
+//         let request = client.raw_client.post(url::CLASS_LIST.as_str()).form(&params).build()?;
 //         let response = client.send(request).await?;
 //
 //         data.session_store.insert(&client.session);
@@ -99,7 +98,7 @@ pub struct CourseRequest {
 //
 //         let request = client
 //             .raw_client
-//             .post(url::SUGGESTED_COURSE)
+//             .post(url::SUGGESTED_COURSE.as_str())
 //             .form(&params)
 //             .build()?;
 //         let response = client.send(request).await?;
@@ -111,7 +110,7 @@ pub struct CourseRequest {
 //     }
 // }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct MajorRequest {
     pub entrance_year: SchoolYear,
     pub account: String,
@@ -120,19 +119,25 @@ pub struct MajorRequest {
 
 #[async_trait]
 impl DoRequest for MajorRequest {
-    async fn process(self, mut data: SharedData) -> ResponseResult {
-        let session = data.session_store.query_or(&self.account, &self.password)?;
-        let mut client = UserClient::new(session, &data.client);
-        client.set_response_hook(Some(default_response_hook));
+    async fn process(self, data: SharedData) -> ResponseResult {
+        // Major list is public, so it's shared by accounts.
+        let fetch = self.fetch(data.clone());
+        let majors = cache::get_or_fetch(&data, "MajorList", &self.entrance_year, fetch).await?;
+
+        Ok(ResponsePayload::MajorList(majors))
+    }
+}
 
-        make_sure_active(&mut client).await?;
+impl MajorRequest {
+    async fn fetch(&self, mut data: SharedData) -> Result<Vec<Major>> {
+        let mut client = active_client(&data, &self.account, &self.password).await?;
 
-        let request = client.raw_client.get(url::MAJOR_LIST).build()?;
+        let request = client.raw_client.get(url::MAJOR_LIST.as_str()).build()?;
         let response = client.send(request).await?;
 
         data.session_store.insert(&client.session)?;
 
         let text = response.text().await?;
-        Ok(ResponsePayload::MajorList(parse_major_list_page(&text)?))
+        parse_major_list_page(&text)
     }
 }
@@ -4,13 +4,11 @@ use serde_json::value::Value;
 
 use crate::agent::SharedData;
 use crate::error::Result;
-use crate::net::client::default_response_hook;
-use crate::net::UserClient;
 use crate::parser::Semester;
 use crate::service::{DoRequest, ResponsePayload, ResponseResult};
-use crate::service::edu::{make_sure_active, url};
+use crate::service::edu::{active_client, url};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ExamArrangeRequest {
     pub account: String,
     pub password: String,
@@ -58,11 +56,7 @@ pub struct ExamArrangement {
 #[async_trait]
 impl DoRequest for ExamArrangeRequest {
     async fn process(self, mut data: SharedData) -> ResponseResult {
-        let session = data.session_store.query_or(&self.account, &self.password)?;
-        let mut client = UserClient::new(session, &data.client);
-        client.set_response_hook(Some(default_response_hook));
-
-        make_sure_active(&mut client).await?;
+        let mut client = active_client(&data, &self.account, &self.password).await?;
 
         let params = [
             ("xnm", self.academic_year.to_string()),
@@ -71,7 +65,7 @@ impl DoRequest for ExamArrangeRequest {
 
         let request = client
             .raw_client
-            .post(url::EXAM_ARRANGEMENT)
+            .post(url::EXAM_ARRANGEMENT.as_str())
             .form(&params)
             .build()?;
         let response = client.send(request).await?;
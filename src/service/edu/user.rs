@@ -2,12 +2,10 @@ use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 
 use crate::agent::SharedData;
-use crate::net::client::default_response_hook;
-use crate::net::UserClient;
 use crate::parser::*;
 use crate::service::{DoRequest, ResponsePayload, ResponseResult};
 
-use super::make_sure_active;
+use super::active_client;
 use super::url;
 
 #[derive(Debug, Deserialize)]
@@ -28,7 +26,7 @@ pub struct ProfileRequest {
 //
 //         make_sure_active(&mut client).await?;
 //
-//         let request = data.client.get(url::PROFILE).build()?;
+//         let request = data.client.get(url::PROFILE.as_str()).build()?;
 //         let response = client.send(request).await?;
 //
 //         // Save session after the last response is received.
@@ -51,18 +49,18 @@ pub struct TimeTableRequest {
 #[async_trait]
 impl DoRequest for TimeTableRequest {
     async fn process(self, mut data: SharedData) -> ResponseResult {
-        let session = data.session_store.query_or(&self.account, &self.password)?;
-        let mut client = UserClient::new(session, &data.client);
-        client.set_response_hook(Some(default_response_hook));
-
-        make_sure_active(&mut client).await?;
+        let mut client = active_client(&data, &self.account, &self.password).await?;
 
         let params = [
             ("xnm", self.school_year.to_string()),
             ("xqm", self.semester.to_raw().to_string()),
         ];
 
-        let request = client.raw_client.post(url::TIME_TABLE).form(&params).build()?;
+        let request = client
+            .raw_client
+            .post(url::TIME_TABLE.as_str())
+            .form(&params)
+            .build()?;
         let response = client.send(request).await?;
 
         // Save session after the last response is received.
@@ -73,7 +71,7 @@ impl DoRequest for TimeTableRequest {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ScoreRequest {
     pub account: String,
     pub password: String,
@@ -84,11 +82,7 @@ pub struct ScoreRequest {
 #[async_trait]
 impl DoRequest for ScoreRequest {
     async fn process(self, mut data: SharedData) -> ResponseResult {
-        let session = data.session_store.query_or(&self.account, &self.password)?;
-        let mut client = UserClient::new(session, &data.client);
-        client.set_response_hook(Some(default_response_hook));
-
-        make_sure_active(&mut client).await?;
+        let mut client = active_client(&data, &self.account, &self.password).await?;
 
         let params = [
             ("xnm", self.school_year.to_string()),
@@ -96,7 +90,7 @@ impl DoRequest for ScoreRequest {
             ("queryModel.showCount", "5000".to_string()),
         ];
 
-        let request = data.client.post(url::SCORE_LIST).form(&params).build()?;
+        let request = data.client.post(url::SCORE_LIST.as_str()).form(&params).build()?;
         let response = client.send(request).await?;
 
         // Save session after the last response is received.
@@ -107,7 +101,7 @@ impl DoRequest for ScoreRequest {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ScoreDetailRequest {
     pub account: String,
     pub password: String,
@@ -119,11 +113,7 @@ pub struct ScoreDetailRequest {
 #[async_trait]
 impl DoRequest for ScoreDetailRequest {
     async fn process(self, mut data: SharedData) -> ResponseResult {
-        let session = data.session_store.query_or(&self.account, &self.password)?;
-        let mut client = UserClient::new(session, &data.client);
-        client.set_response_hook(Some(default_response_hook));
-
-        make_sure_active(&mut client).await?;
+        let mut client = active_client(&data, &self.account, &self.password).await?;
 
         let params = [
             ("jxb_id", self.class_id),
@@ -131,7 +121,11 @@ impl DoRequest for ScoreDetailRequest {
             ("xqm", self.semester.to_raw().to_string()),
         ];
 
-        let request = data.client.post(url::SCORE_DETAIL).form(&params).build()?;
+        let request = data
+            .client
+            .post(url::SCORE_DETAIL.as_str())
+            .form(&params)
+            .build()?;
         let response = client.send(request).await?;
         let html = response.text().await?;
 
@@ -22,6 +22,12 @@ pub enum ActionError {
     ParsingError = 55,
     #[error("参数错误")]
     BadParameter = 56,
+    #[error("请求超时")]
+    Timeout = 57,
+    #[error("请求已取消")]
+    Cancelled = 58,
+    #[error("代理繁忙, 请稍后再试")]
+    Busy = 59,
 }
 
 /// Error code and message to response
@@ -3,17 +3,21 @@ use serde::{Deserialize, Serialize};
 
 use crate::agent::SharedData;
 use crate::error::Result;
-use crate::net::client::default_response_hook;
 use crate::net::UserClient;
 use crate::parser::{ExpensePage, Parse};
-use crate::service::{DoRequest, ResponsePayload, ResponseResult};
+use crate::service::{batch, CampusSystem, DoRequest, ResponseChunk, ResponsePayload, ResponseResult};
+
+/// Max pages sent in a streamed response.
+const MAX_STREAM_PAGES: u32 = 200;
 
 mod url {
-    use const_format::concatcp;
+    use crate::config::CONFIG;
 
-    pub const OA_HOME: &str = "https://myportal.sit.edu.cn/";
-    pub const CARD_HOME: &str = "http://card.sit.edu.cn";
-    pub const EXPENSE_PAGE: &str = concatcp!(CARD_HOME, "/personalxiaofei.jsp");
+    lazy_static! {
+        pub static ref OA_HOME: String = format!("{}/", CONFIG.endpoints.portal);
+        pub static ref CARD_HOME: &'static str = &CONFIG.endpoints.card;
+        pub static ref EXPENSE_PAGE: String = format!("{}/personalxiaofei.jsp", *CARD_HOME);
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -32,10 +36,13 @@ pub struct ExpenseRequest {
 
 impl ExpenseRequest {
     pub fn build_url(&self) -> Url {
+        self.build_page_url(self.page)
+    }
+
+    fn build_page_url(&self, page: Option<u32>) -> Url {
         let mut params: Vec<(&str, String)> = vec![];
 
-        // self.page.and_then(|x|  params.push(("page", x.to_string())));
-        if let Some(p) = self.page {
+        if let Some(p) = page {
             params.push(("page", p.to_string()));
         }
         if let Some(st) = self.start_time.clone() {
@@ -45,41 +52,69 @@ impl ExpenseRequest {
             params.push(("to", et));
         }
 
-        Url::parse_with_params(url::EXPENSE_PAGE, params.iter()).unwrap()
+        Url::parse_with_params(&url::EXPENSE_PAGE, params.iter()).unwrap()
     }
 }
 
 async fn make_sure_active(client: &mut UserClient) -> Result<()> {
     // If OA home is accessible, card home is ensured to be accessed.
-    let home_request = client.raw_client.get(url::OA_HOME).build()?;
+    let home_request = client.raw_client.get(url::OA_HOME.as_str()).build()?;
     let response = client.send(home_request).await?;
 
     if response.url().path() != "/" {
         // The session is already expired, re-login now.
         client.login_with_session().await?;
 
-        let home_request = client.raw_client.get(url::OA_HOME).build()?;
+        let home_request = client.raw_client.get(url::OA_HOME.as_str()).build()?;
         let _ = client.send(home_request).await?;
     }
     Ok(())
 }
 
+/// Client of the account with an active session on card.sit.edu.cn.
+async fn active_client(data: &SharedData, account: &str, password: &str) -> Result<UserClient> {
+    batch::active_client(data, account, password, CampusSystem::Card, |client| {
+        Box::pin(make_sure_active(client))
+    })
+    .await
+}
+
+async fn fetch_page(client: &mut UserClient, url: Url) -> Result<ExpensePage> {
+    let request = client.raw_client.get(url).build()?;
+    let response = client.send(request).await?;
+    let html = response.text().await?;
+
+    ExpensePage::from_html(&html)
+}
+
 #[async_trait::async_trait]
 impl DoRequest for ExpenseRequest {
     async fn process(self, mut data: SharedData) -> ResponseResult {
-        let session = data.session_store.query_or(&self.account, &self.password)?;
-        let mut client = UserClient::new(session, &data.client);
-
-        client.set_response_hook(Some(default_response_hook));
-        make_sure_active(&mut client).await?;
+        let mut client = active_client(&data, &self.account, &self.password).await?;
 
         data.session_store.insert(&client.session)?;
 
-        let request = client.raw_client.get(self.build_url()).build()?;
-        let response = client.send(request).await?;
-        let html = response.text().await?;
+        if let (Some(stream), None) = (data.stream.take(), self.page) {
+            // Send the whole history page by page. The page count is taken from the first page, and
+            // capped in case the card system misbehaves.
+            let mut total = MAX_STREAM_PAGES;
+            for page in 1.. {
+                let expense_page = fetch_page(&mut client, self.build_page_url(Some(page))).await?;
+                if page == 1 {
+                    total = total.min(expense_page.page.total as u32);
+                }
+                let empty = expense_page.records.is_empty();
 
-        let expense_page = ExpensePage::from_html(&html)?;
+                stream
+                    .send(ResponseChunk::Payload(ResponsePayload::CardExpense(expense_page)))
+                    .await?;
+                if empty || page >= total {
+                    break;
+                }
+            }
+            return Ok(stream.finish());
+        }
+        let expense_page = fetch_page(&mut client, self.build_url()).await?;
         Ok(ResponsePayload::CardExpense(expense_page))
     }
 }
@@ -0,0 +1,383 @@
+//! Durable jobs, for requests to be done eventually even when the campus system is down.
+//!
+//! A request marked `Eventually` is saved in the sled database and answered with a job id at once.
+//! The background task `run` processes saved jobs, and retries failed ones with backoff. The result
+//! is pushed to server with `AgentEvent::JobFinished`, and can be polled by `JobStatus` until it's
+//! removed after `jobs.keep` seconds. Jobs survive restart of agent.
+//!
+//! Passwords are not saved with jobs. The password of the account is kept in its session, which is
+//! where agent stores credentials anyway, and put back into the request on each attempt.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::time::Duration;
+
+use chrono::Utc;
+use futures::stream::{FuturesUnordered, StreamExt};
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+use crate::agent::backoff::Backoff;
+use crate::agent::event::{self, AgentEvent};
+use crate::agent::{self, SharedData};
+use crate::config::CONFIG;
+use crate::error::Result;
+
+use super::{ActionError, CampusSystem, ErrorResponse, RequestPayload, ResponsePayload, ResponseResult};
+
+/// Name of sled tree storing jobs.
+const JOB_TREE: &str = "jobs";
+/// Max time to sleep when no job is due, in seconds.
+const IDLE_INTERVAL: i64 = 60;
+
+lazy_static! {
+    /// Wake the runner when a job is submitted.
+    static ref NEW_JOB: Notify = Notify::new();
+}
+
+/// Status of a job.
+#[derive(Debug, Serialize)]
+pub enum JobStatus {
+    /// Waiting for the next attempt, at the unix timestamp.
+    Pending { attempts: u32, next_attempt: i64 },
+    /// Result in JSON encoded `ResponseResult`.
+    Finished(String),
+    /// No such job, or it has been removed.
+    NotFound,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Job {
+    /// JSON encoded `RequestPayload`, without the password.
+    request: Value,
+    attempts: u32,
+    /// Unix timestamp of the next attempt.
+    next_attempt: i64,
+    /// Result in JSON encoded `ResponseResult`, once finished.
+    result: Option<String>,
+    finished_at: Option<i64>,
+}
+
+fn open_tree(data: &SharedData) -> Result<sled::Tree> {
+    data.session_store.open_tree(JOB_TREE)
+}
+
+fn load(tree: &sled::Tree, id: u64) -> Result<Option<Job>> {
+    match tree.get(id.to_be_bytes())? {
+        Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+        None => Ok(None),
+    }
+}
+
+fn save(tree: &sled::Tree, id: u64, job: &Job) -> Result<()> {
+    tree.insert(id.to_be_bytes(), serde_json::to_vec(job)?)?;
+    Ok(())
+}
+
+/// Fields of the request, such as `account`, in `{ "<Kind>": { ... } }`.
+fn fields_of(request: &mut Value) -> Option<&mut Map<String, Value>> {
+    request.as_object_mut()?.values_mut().next()?.as_object_mut()
+}
+
+/// Remove the password from the request, and return it with the account.
+fn take_password(request: &mut Value) -> Option<(String, String)> {
+    let fields = fields_of(request)?;
+    let account = fields.get("account")?.as_str()?.to_string();
+
+    match fields.remove("password")? {
+        Value::String(password) => Some((account, password)),
+        _ => None,
+    }
+}
+
+/// Put the password of the account back into the request, from its session.
+fn restore_password(request: &mut Value, data: &SharedData) -> std::result::Result<(), ErrorResponse> {
+    let fields = match fields_of(request) {
+        Some(fields) if !fields.contains_key("password") => fields,
+        _ => return Ok(()),
+    };
+    let account = match fields.get("account").and_then(|account| account.as_str()) {
+        Some(account) => account,
+        None => return Ok(()),
+    };
+    // The session may be removed by clearing the database.
+    let session = data
+        .session_store
+        .query(account)?
+        .ok_or(ActionError::LoginFailed)?;
+
+    fields.insert("password".to_string(), session.password.into());
+    Ok(())
+}
+
+/// Only requests reading data from campus systems can be done later. Joining an activity or login
+/// is meaningful only at the time.
+fn is_deferrable(request: &RequestPayload) -> bool {
+    match request.target() {
+        Some(CampusSystem::AuthServer) | None => false,
+        Some(_) => !matches!(request, RequestPayload::ScActivityJoin(_)),
+    }
+}
+
+/// Errors which retrying does not help.
+fn is_permanent(e: &ErrorResponse) -> bool {
+    [
+        ActionError::BadRequest,
+        ActionError::LoginFailed,
+        ActionError::BadParameter,
+    ]
+    .iter()
+    .any(|permanent| permanent.to_u16() == Some(e.code))
+}
+
+/// Save the request as a job, which is processed by `run` as soon as possible.
+pub(super) fn submit(request: RequestPayload, data: &SharedData) -> ResponseResult {
+    if !is_deferrable(&request) {
+        return Err(ActionError::BadParameter.into());
+    }
+    let mut saved = serde_json::to_value(&request)?;
+
+    if let Some((account, password)) = take_password(&mut saved) {
+        let mut store = data.session_store.clone();
+        let session = store.query_or(&account, &password)?;
+
+        store.insert(&session)?;
+    }
+    let job = Job {
+        request: saved,
+        attempts: 0,
+        next_attempt: Utc::now().timestamp(),
+        result: None,
+        finished_at: None,
+    };
+    let id = data.session_store.generate_id()?;
+
+    save(&open_tree(data)?, id, &job)?;
+    NEW_JOB.notify_one();
+    info!(id, kind = request.kind(), "Job submitted.");
+    Ok(ResponsePayload::JobAccepted(id))
+}
+
+pub(super) fn status(id: u64, data: &SharedData) -> ResponseResult {
+    let status = match load(&open_tree(data)?, id)? {
+        Some(Job {
+            result: Some(result), ..
+        }) => JobStatus::Finished(result),
+        Some(job) => JobStatus::Pending {
+            attempts: job.attempts,
+            next_attempt: job.next_attempt,
+        },
+        None => JobStatus::NotFound,
+    };
+    Ok(ResponsePayload::Job(status))
+}
+
+/// Make an attempt of the job, and schedule the next one if it fails.
+async fn attempt(tree: &sled::Tree, id: u64, mut job: Job, data: SharedData) -> Result<()> {
+    let mut request = job.request.clone();
+    let result = match restore_password(&mut request, &data) {
+        Ok(()) => match serde_json::from_value::<RequestPayload>(request) {
+            Ok(request) => agent::dispatch_limited(request, data).await,
+            // Saved by an older version with different request types.
+            Err(_) => Err(ActionError::BadRequest.into()),
+        },
+        Err(e) => Err(e),
+    };
+    let config = &CONFIG.jobs;
+    let now = Utc::now().timestamp();
+
+    job.attempts += 1;
+    match result {
+        Err(e) if !is_permanent(&e) && job.attempts < config.max_attempts => {
+            let min = Duration::from_secs(config.retry_min);
+            let max = Duration::from_secs(config.retry_max);
+            let delay = Backoff::resume(min, max, job.attempts - 1).next_delay();
+
+            job.next_attempt = now + delay.as_secs() as i64;
+            warn!(
+                id,
+                attempts = job.attempts,
+                "Job failed, retry in {}s: {}",
+                delay.as_secs(),
+                e
+            );
+        }
+        result => {
+            let result = serde_json::to_string(&result)?;
+
+            info!(id, attempts = job.attempts, "Job finished.");
+            job.result = Some(result.clone());
+            job.finished_at = Some(now);
+            event::emit(AgentEvent::JobFinished { id, result });
+        }
+    }
+    save(tree, id, &job)
+}
+
+/// Remove the entry, which is not a valid job or has expired.
+fn remove(tree: &sled::Tree, key: &[u8]) {
+    if let Err(e) = tree.remove(key) {
+        warn!("Failed to remove job: {}", e);
+    }
+}
+
+/// Collect due jobs, except those in `held` until the time, and remove expired ones. Return the
+/// due jobs and the time to scan again.
+fn scan(tree: &sled::Tree, now: i64, held: &HashMap<u64, i64>) -> (Vec<(u64, Job)>, i64) {
+    let mut wake_at = now + IDLE_INTERVAL;
+    let mut due = Vec::new();
+
+    for item in tree.iter() {
+        let (key, value) = match item {
+            Ok(item) => item,
+            Err(e) => {
+                warn!("Failed to read jobs: {}", e);
+                break;
+            }
+        };
+        let id = match key.as_ref().try_into() {
+            Ok(key) => u64::from_be_bytes(key),
+            Err(_) => {
+                warn!(key = ?key.as_ref(), "Remove invalid job key.");
+                remove(tree, &key);
+                continue;
+            }
+        };
+        if let Some(until) = held.get(&id) {
+            wake_at = wake_at.min(*until);
+            continue;
+        }
+
+        match serde_json::from_slice::<Job>(&value) {
+            Ok(Job {
+                finished_at: Some(finished_at),
+                ..
+            }) => {
+                if finished_at + CONFIG.jobs.keep as i64 <= now {
+                    remove(tree, &key);
+                }
+            }
+            Ok(job) if job.next_attempt <= now => due.push((id, job)),
+            Ok(job) => wake_at = wake_at.min(job.next_attempt),
+            Err(e) => {
+                warn!(id, "Remove job which can't be decoded: {}", e);
+                remove(tree, &key);
+            }
+        }
+    }
+    (due, wake_at)
+}
+
+/// Process saved jobs until the agent exits. Attempts run concurrently, and jobs submitted
+/// meanwhile are started without waiting for them.
+pub async fn run(mut data: SharedData) -> Result<()> {
+    let tree = open_tree(&data)?;
+    let mut attempts = FuturesUnordered::new();
+    // Jobs not to be scanned until the time: running ones, and those failed to save.
+    let mut held: HashMap<u64, i64> = HashMap::new();
+
+    loop {
+        let now = Utc::now().timestamp();
+        held.retain(|_, until| *until > now);
+
+        let (due, wake_at) = scan(&tree, now, &held);
+        if !due.is_empty() {
+            data.refresh();
+        }
+        for (id, job) in due {
+            let (tree, data) = (&tree, data.clone());
+
+            held.insert(id, i64::MAX);
+            attempts.push(async move { (id, attempt(tree, id, job, data).await) });
+        }
+
+        let sleep = Duration::from_secs((wake_at - now).max(1) as u64);
+        tokio::select! {
+            _ = NEW_JOB.notified() => {}
+            _ = tokio::time::sleep(sleep) => {}
+            Some((id, result)) = attempts.next(), if !attempts.is_empty() => {
+                held.remove(&id);
+                if let Err(e) = result {
+                    // Try again later rather than at once, since the database may be broken.
+                    warn!(id, "Failed to save job: {:#}", e);
+                    held.insert(id, Utc::now().timestamp() + IDLE_INTERVAL);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use std::collections::HashMap;
+
+    use super::{is_deferrable, is_permanent, save, scan, take_password, Job};
+    use crate::service::{ActionError, ErrorResponse, RequestPayload};
+
+    #[test]
+    fn test_deferrable() {
+        let request = |kind, parameter| RequestPayload::from_json(kind, parameter).unwrap();
+        let list = request("ActivityList", json!({ "count": 10, "index": 1, "category": 0 }));
+        let auth = request("PortalAuth", json!({ "account": "", "credential": "" }));
+
+        assert!(!is_deferrable(&auth));
+        assert!(!is_deferrable(&request("Ping", json!(""))));
+        assert!(!is_deferrable(&RequestPayload::Eventually(Box::new(request(
+            "Ping",
+            json!("")
+        )))));
+        assert!(is_deferrable(&list));
+
+        assert!(is_permanent(&ActionError::LoginFailed.into()));
+        assert!(!is_permanent(&ActionError::Busy.into()));
+        assert!(!is_permanent(&ErrorResponse {
+            code: 1,
+            msg: String::new(),
+        }));
+    }
+
+    #[test]
+    fn test_take_password() {
+        let mut request = json!({ "Score": { "account": "2010", "password": "secret", "semester": 1 } });
+
+        assert_eq!(
+            take_password(&mut request),
+            Some(("2010".to_string(), "secret".to_string()))
+        );
+        assert_eq!(request, json!({ "Score": { "account": "2010", "semester": 1 } }));
+        assert_eq!(take_password(&mut json!({ "Ping": "" })), None);
+    }
+
+    #[test]
+    fn test_scan() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let tree = db.open_tree("jobs").unwrap();
+        let job = |next_attempt| Job {
+            request: json!({ "Ping": "" }),
+            attempts: 0,
+            next_attempt,
+            result: None,
+            finished_at: None,
+        };
+
+        save(&tree, 1, &job(100)).unwrap();
+        save(&tree, 2, &job(100)).unwrap();
+        save(&tree, 3, &job(150)).unwrap();
+        tree.insert(4u64.to_be_bytes(), b"broken".as_ref()).unwrap();
+        tree.insert(b"bad key", b"".as_ref()).unwrap();
+
+        // Job 2 is running.
+        let held: HashMap<u64, i64> = vec![(2, i64::MAX)].into_iter().collect();
+        let (due, wake_at) = scan(&tree, 120, &held);
+
+        assert_eq!(due.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(wake_at, 150);
+        // Invalid entries are removed, and others are kept.
+        assert_eq!(tree.len(), 3);
+    }
+}
@@ -5,25 +5,27 @@ use strum_macros::{Display, EnumVariantNames};
 
 use crate::agent::SharedData;
 use crate::parser::{HoldingPreviews, Parse, SearchLibraryResult};
-use crate::service::{DoRequest, ResponsePayload, ResponseResult};
+use crate::service::{cache, DoRequest, ResponsePayload, ResponseResult};
 
 mod url {
-    use const_format::concatcp;
+    use crate::config::CONFIG;
 
-    /// 图书馆url
-    pub const HOME: &str = "http://210.35.66.106";
+    lazy_static! {
+        /// 图书馆url
+        pub static ref HOME: &'static str = &CONFIG.endpoints.library;
 
-    /// 图书馆馆藏检索页面
-    pub const OPAC: &str = concatcp!(HOME, "/opac");
+        /// 图书馆馆藏检索页面
+        pub static ref OPAC: String = format!("{}/opac", *HOME);
 
-    /// 搜索结果页
-    pub const SEARCH: &str = concatcp!(OPAC, "/search");
+        /// 搜索结果页
+        pub static ref SEARCH: String = format!("{}/search", *OPAC);
 
-    /// 图书信息页
-    pub const BOOK: &str = concatcp!(OPAC, "/book");
+        /// 图书信息页
+        pub static ref BOOK: String = format!("{}/book", *OPAC);
 
-    /// 借阅信息查询
-    pub const HOLDING_PREVIEW: &str = concatcp!(BOOK, "/holdingPreviews");
+        /// 借阅信息查询
+        pub static ref HOLDING_PREVIEW: String = format!("{}/holdingPreviews", *BOOK);
+    }
 }
 
 /// 搜索方式
@@ -179,7 +181,7 @@ impl SearchLibraryRequest {
     }
     pub fn build_url(&self) -> Url {
         Url::parse_with_params(
-            url::SEARCH,
+            &url::SEARCH,
             [
                 ("q", self.keyword.as_str()),
                 ("searchType", "standard"),
@@ -202,6 +204,13 @@ impl SearchLibraryRequest {
 #[async_trait::async_trait]
 impl DoRequest for SearchLibraryRequest {
     async fn process(self, data: SharedData) -> ResponseResult {
+        let books = cache::get_or_fetch(&data, "SearchLibrary", &self, self.search(&data)).await?;
+        Ok(ResponsePayload::SearchLibrary(books))
+    }
+}
+
+impl SearchLibraryRequest {
+    async fn search(&self, data: &SharedData) -> Result<SearchLibraryResult> {
         let request = data.client.get(self.build_url()).build()?;
         let response = data.client.execute(request).await?;
         let html = response.text().await?;
@@ -225,7 +234,7 @@ impl DoRequest for SearchLibraryRequest {
         //             .unwrap()
         //             .clone();
         //     });
-        Ok(ResponsePayload::SearchLibrary(books))
+        Ok(books)
     }
 }
 
@@ -248,7 +257,7 @@ async fn get_holding_previews(book_id_list: Vec<String>, data: &SharedData) -> R
         book_id_list_str.push_str(",");
     });
     let url = Url::parse_with_params(
-        url::HOLDING_PREVIEW,
+        &url::HOLDING_PREVIEW,
         [
             ("bookrecnos", book_id_list_str),
             ("curLibcodes", "".to_string()),
@@ -273,7 +282,7 @@ impl DoRequest for BookHoldingRequest {
             book_id_list_str.push_str(",");
         });
         let url = Url::parse_with_params(
-            url::HOLDING_PREVIEW,
+            &url::HOLDING_PREVIEW,
             [
                 ("bookrecnos", book_id_list_str),
                 ("curLibcodes", "".to_string()),
@@ -1,19 +1,134 @@
-use crate::agent::SharedData;
-use crate::service::{DoRequest, ResponsePayload, ResponseResult};
+use std::time::{Duration, Instant};
+
 use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+
+use crate::agent::handshake::{supported_request_kinds, AGENT_VERSION};
+use crate::agent::status::{self, ConnectionState};
+use crate::agent::{live, SharedData};
+use crate::config::CONFIG;
+use crate::metrics;
+use crate::net::proxy::{self, ProxyStatus, RouteInfo};
+use crate::net::{test_network_connectivity, NetworkConnectivity};
+use crate::service::{CampusSystem, DoRequest, ResponsePayload, ResponseResult};
+
+/// Timeout of each health check.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+lazy_static! {
+    /// Start time of agent, touched in `main`.
+    pub static ref STARTED_AT: Instant = Instant::now();
+}
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct AgentInfoRequest;
 
 #[derive(Debug, Serialize)]
 pub struct AgentInfo {
     pub name: String,
+    pub version: String,
+    /// Seconds since agent started.
+    pub uptime: u64,
+    /// Sessions in storage.
+    pub sessions: usize,
+    /// Unset if probes are disabled by `agent.probe`.
+    pub connectivity: Option<NetworkConnectivity>,
+    pub systems: Vec<SystemHealth>,
+    /// Requests being processed on server connections, including this one.
+    pub in_flight: i64,
+    /// Connections to server, with the endpoints in use.
+    pub workers: Vec<WorkerInfo>,
+    /// Proxy of hosts other than campus systems, without credentials.
+    pub proxy: Option<String>,
+    /// Health of egress proxies in the pool.
+    pub proxy_pool: Vec<ProxyStatus>,
+    /// Request kinds the agent can handle.
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorkerInfo {
+    pub id: usize,
+    pub state: ConnectionState,
+    /// Endpoint connecting or connected to, which may be a fallback one.
+    pub endpoint: Option<String>,
+    /// Consecutive failed reconnect rounds.
+    pub reconnect_attempts: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SystemHealth {
+    pub system: CampusSystem,
+    /// "direct", "pool", "agent.proxy" or the proxy name.
+    pub route: String,
+    /// Proxy url without credentials. Unset if direct or through the pool.
+    pub proxy: Option<String>,
+    /// Unset if probes are disabled by `agent.probe`.
+    pub reachable: Option<bool>,
+    /// Time to get the response of home page, in milliseconds.
+    pub latency: Option<u64>,
+}
+
+/// Send a request to the home page of the system. Any HTTP response means it's reachable.
+async fn probe(client: &reqwest::Client, system: CampusSystem, route: RouteInfo) -> SystemHealth {
+    let mut health = SystemHealth {
+        system,
+        route: route.route,
+        proxy: route.proxy,
+        reachable: None,
+        latency: None,
+    };
+    if !CONFIG.agent.probe {
+        return health;
+    }
+    let start = Instant::now();
+    let response = client.get(system.home()).timeout(PROBE_TIMEOUT).send().await;
+
+    health.reachable = Some(response.is_ok());
+    health.latency = response.ok().map(|_| start.elapsed().as_millis() as u64);
+    health
+}
+
+fn workers() -> Vec<WorkerInfo> {
+    status::snapshot()
+        .into_iter()
+        .map(|(id, status)| WorkerInfo {
+            id,
+            state: status.state,
+            endpoint: status.endpoint,
+            reconnect_attempts: status.reconnect_attempts,
+        })
+        .collect()
 }
 
 #[async_trait::async_trait]
 impl DoRequest for AgentInfoRequest {
     async fn process(self, data: SharedData) -> ResponseResult {
-        let agent_info = AgentInfo { name: data.node };
+        let connectivity = async {
+            if !CONFIG.agent.probe {
+                return None;
+            }
+            let connectivity = tokio::time::timeout(PROBE_TIMEOUT, test_network_connectivity()).await;
+            Some(connectivity.unwrap_or(NetworkConnectivity::NoConnection))
+        };
+        let routes = live::routes();
+        let systems = CampusSystem::iter()
+            .map(|system| probe(&data.client, system, routes.systems[&system].clone()));
+        let (connectivity, systems) = tokio::join!(connectivity, futures::future::join_all(systems));
+
+        let agent_info = AgentInfo {
+            name: data.node,
+            version: AGENT_VERSION.to_string(),
+            uptime: STARTED_AT.elapsed().as_secs(),
+            sessions: data.session_store.len(),
+            connectivity,
+            systems,
+            in_flight: metrics::IN_FLIGHT.get(),
+            workers: workers(),
+            proxy: routes.default.proxy.clone(),
+            proxy_pool: proxy::pool_status(),
+            capabilities: supported_request_kinds(),
+        };
         Ok(ResponsePayload::Credential(agent_info))
     }
 }
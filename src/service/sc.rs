@@ -1,5 +1,5 @@
 use reqwest::StatusCode;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::agent::SharedData;
 use crate::error::Result;
@@ -9,7 +9,9 @@ use crate::net::UserClient;
 use crate::parser::{
     Activity, ActivityDetail, get_my_activity_list, get_my_score_list, Parse, ScImages, ScJoinResult,
 };
-use crate::service::{ActionError, DoRequest, ResponsePayload};
+use crate::service::{
+    batch, cache, ActionError, CampusSystem, DoRequest, ResponseChunk, ResponsePayload,
+};
 
 use super::ResponseResult;
 
@@ -29,21 +31,34 @@ const CATEGORY_MAPPING: &[&str] = &[
 ];
 
 mod url {
-    pub const SSO_SC_REDIRECT: &str =
-        "https://authserver.sit.edu.cn/authserver/login?service=http%3A%2F%2Fsc.sit.edu.cn%2F";
-
-    pub const MY_SCORE: &str = "http://sc.sit.edu.cn/public/pcenter/scoreDetail.action";
-
-    pub const MY_ACTIVITY: &str =
-        "http://sc.sit.edu.cn/public/pcenter/activityOrderList.action?pageSize=200";
-
-    pub const APPLY_ACTIVITY: &str = "http://sc.sit.edu.cn/public/pcenter/checkUser.action?activityId=";
-
-    pub const APPLY_SUCCESS: &str =
-        "http://sc.sit.edu.cn/public/pcenter/applyActivity.action?activityId=";
+    use crate::config::CONFIG;
+
+    lazy_static! {
+        pub static ref HOME: &'static str = &CONFIG.endpoints.sc;
+        /// Host of `HOME`, which is checked on loading config.
+        pub static ref HOST: String = reqwest::Url::parse(*HOME)
+            .ok()
+            .and_then(|home| home.host_str().map(ToString::to_string))
+            .unwrap_or_default();
+        pub static ref SSO_SC_REDIRECT: String = format!(
+            "{}/authserver/login?service={}",
+            CONFIG.endpoints.authserver,
+            urlencoding::encode(&format!("{}/", *HOME))
+        );
+        pub static ref MY_SCORE: String = format!("{}/public/pcenter/scoreDetail.action", *HOME);
+        pub static ref MY_ACTIVITY: String =
+            format!("{}/public/pcenter/activityOrderList.action?pageSize=200", *HOME);
+        pub static ref APPLY_ACTIVITY: String =
+            format!("{}/public/pcenter/checkUser.action?activityId=", *HOME);
+        pub static ref APPLY_SUCCESS: String =
+            format!("{}/public/pcenter/applyActivity.action?activityId=", *HOME);
+        pub static ref ACTIVITY_LIST: String = format!("{}/public/activity/activityList.action", *HOME);
+        pub static ref ACTIVITY_DETAIL: String =
+            format!("{}/public/activity/activityDetail.action?activityId=", *HOME);
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ActivityListRequest {
     /// Count of activities per page.
     pub count: u16,
@@ -54,16 +69,24 @@ pub struct ActivityListRequest {
 }
 
 async fn make_sure_active(client: &mut UserClient) -> Result<()> {
-    let home_request = client.raw_client.get(url::SSO_SC_REDIRECT).build()?;
+    let home_request = client.raw_client.get(url::SSO_SC_REDIRECT.as_str()).build()?;
     let response = client.send(home_request).await?;
-    if response.url().as_str() == url::SSO_SC_REDIRECT {
+    if response.url().as_str() == *url::SSO_SC_REDIRECT {
         client.login_with_session().await?;
-        let request = client.raw_client.get(url::SSO_SC_REDIRECT).build()?;
+        let request = client.raw_client.get(url::SSO_SC_REDIRECT.as_str()).build()?;
         let _ = client.send(request).await?;
     }
     Ok(())
 }
 
+/// Client of the account with an active session on sc.sit.edu.cn.
+async fn active_client(data: &SharedData, account: &str, password: &str) -> Result<UserClient> {
+    batch::active_client(data, account, password, CampusSystem::SecondClass, |client| {
+        Box::pin(make_sure_active(client))
+    })
+    .await
+}
+
 // When we fetch activity detail page, it costs lot if we go to SSO_SC_REDIRECT to checkout whether
 // we can access the page. So it's better to fetch first, and then decide to redirect.
 async fn fetch_or_make_sure_active(
@@ -98,7 +121,7 @@ async fn fetch_image(images: &mut Vec<ScImages>, mut client: UserClient) -> Resu
             match content {
                 Ok(result) => image.content = result,
                 Err(e) => {
-                    println!("{:?}", e);
+                    tracing::warn!("Could not download image: {}", e);
                 }
             }
         }
@@ -120,18 +143,27 @@ async fn download_image(image_url: String, client: &mut UserClient) -> Result<Ve
 
 fn match_image_url(old_name: &str) -> String {
     let image_url;
-    if old_name.contains("sc.sit.edu.cn") || old_name.contains("job.sit.edu.cn") {
+    if old_name.contains(url::HOST.as_str()) || old_name.contains("job.sit.edu.cn") {
         image_url = old_name.to_string();
     } else {
-        image_url = format!("http://sc.sit.edu.cn{}", old_name);
+        image_url = format!("{}{}", *url::HOME, old_name);
     }
     image_url
 }
 
 #[async_trait::async_trait]
 impl DoRequest for ActivityListRequest {
+    async fn process(self, data: SharedData) -> ResponseResult {
+        let fetch = self.fetch(data.clone());
+        let activities = cache::get_or_fetch(&data, "ActivityList", &self, fetch).await?;
+
+        Ok(ResponsePayload::ActivityList(activities))
+    }
+}
+
+impl ActivityListRequest {
     /// Fetch and parse activity list page.
-    async fn process(self, mut data: SharedData) -> ResponseResult {
+    async fn fetch(&self, mut data: SharedData) -> Result<Vec<Activity>> {
         let session = data
             .session_store
             .choose_randomly()?
@@ -144,7 +176,8 @@ impl DoRequest for ActivityListRequest {
         let request = client
             .raw_client
             .get(&format!(
-                "http://sc.sit.edu.cn/public/activity/activityList.action?{}",
+                "{}?{}",
+                *url::ACTIVITY_LIST,
                 make_parameter!("pageNo" => &self.index.to_string(),"pageSize" => &self.count.to_string(),
                     "categoryId" => category_id.as_str()
                 )
@@ -163,11 +196,11 @@ impl DoRequest for ActivityListRequest {
                 s
             })
             .collect();
-        Ok(ResponsePayload::ActivityList(result))
+        Ok(result)
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ActivityDetailRequest {
     /// Activity id in sc.sit.edu.cn
     pub id: i32,
@@ -175,18 +208,67 @@ pub struct ActivityDetailRequest {
 
 #[async_trait::async_trait]
 impl DoRequest for ActivityDetailRequest {
-    /// Fetch and parse activity detail page.
     async fn process(self, mut data: SharedData) -> ResponseResult {
-        let session = data
-            .session_store
-            .choose_randomly()?
-            .ok_or(ActionError::NoSessionAvailable)?;
-        let mut client = UserClient::new(session, &data.client);
+        let stream = match data.stream.take() {
+            Some(stream) => stream,
+            None => {
+                // Streamed responses are not cached, since images are sent separately. Images are
+                // not cached either, which would take many times the space of the detail in JSON.
+                let mut fetched_client = None;
+                let fetch = async {
+                    let (activity, client) = self.fetch(data.clone()).await?;
+                    fetched_client = Some(client);
+                    Ok(activity)
+                };
+                let mut activity = cache::get_or_fetch(&data, "ActivityDetail", &self, fetch).await?;
+                let client = match fetched_client {
+                    Some(client) => client,
+                    None => random_client(&mut data)?,
+                };
+                fetch_image(&mut activity.images, client).await?;
+                return Ok(ResponsePayload::ActivityDetail(Box::from(activity)));
+            }
+        };
+
+        // Send the detail without images first, and then images one by one.
+        let (activity, mut client) = self.fetch(data).await?;
+        let images: Vec<(String, String)> = activity
+            .images
+            .iter()
+            .map(|image| (image.new_name.clone(), match_image_url(&image.old_name)))
+            .collect();
+        stream
+            .send(ResponseChunk::Payload(ResponsePayload::ActivityDetail(
+                Box::from(activity),
+            )))
+            .await?;
+
+        for (name, image_url) in images {
+            match download_image(image_url, &mut client).await {
+                Ok(content) => stream.send_blob(&name, &content).await?,
+                Err(e) => tracing::warn!("Could not download image: {}", e),
+            }
+        }
+        Ok(stream.finish())
+    }
+}
 
-        let url = format!(
-            "http://sc.sit.edu.cn/public/activity/activityDetail.action?activityId={}",
-            self.id
-        );
+/// Client of any account in storage, for public pages.
+fn random_client(data: &mut SharedData) -> Result<UserClient> {
+    let session = data
+        .session_store
+        .choose_randomly()?
+        .ok_or(ActionError::NoSessionAvailable)?;
+
+    Ok(UserClient::new(session, &data.client))
+}
+
+impl ActivityDetailRequest {
+    /// Fetch and parse activity detail page, without images. Return the client to download them.
+    async fn fetch(&self, mut data: SharedData) -> Result<(ActivityDetail, UserClient)> {
+        let mut client = random_client(&mut data)?;
+
+        let url = format!("{}{}", *url::ACTIVITY_DETAIL, self.id);
         let mut response = fetch_or_make_sure_active(&mut client, &url).await?;
         if response.is_none() {
             client.set_response_hook(Some(default_response_hook));
@@ -199,14 +281,12 @@ impl DoRequest for ActivityDetailRequest {
 
         data.session_store.insert(&client.session)?;
 
-        let mut activity: ActivityDetail = Parse::from_html(&html)?;
-        fetch_image(&mut activity.images, client).await?;
-
-        Ok(ResponsePayload::ActivityDetail(Box::from(activity)))
+        let activity: ActivityDetail = Parse::from_html(&html)?;
+        Ok((activity, client))
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ScScoreItemRequest {
     pub account: String,
     pub password: String,
@@ -215,13 +295,9 @@ pub struct ScScoreItemRequest {
 #[async_trait::async_trait]
 impl DoRequest for ScScoreItemRequest {
     async fn process(self, mut data: SharedData) -> ResponseResult {
-        let session = data.session_store.query_or(&self.account, &self.password)?;
-        let mut client = UserClient::new(session, &data.client);
-        client.set_response_hook(Some(default_response_hook));
+        let mut client = active_client(&data, &self.account, &self.password).await?;
 
-        make_sure_active(&mut client).await?;
-
-        let request = client.raw_client.get(url::MY_SCORE).build()?;
+        let request = client.raw_client.get(url::MY_SCORE.as_str()).build()?;
         let response = client.send(request).await?;
         let html = response.text().await?;
 
@@ -232,7 +308,7 @@ impl DoRequest for ScScoreItemRequest {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ScActivityRequest {
     pub account: String,
     pub password: String,
@@ -241,13 +317,9 @@ pub struct ScActivityRequest {
 #[async_trait::async_trait]
 impl DoRequest for ScActivityRequest {
     async fn process(self, mut data: SharedData) -> ResponseResult {
-        let session = data.session_store.query_or(&self.account, &self.password)?;
-        let mut client = UserClient::new(session, &data.client);
-        client.set_response_hook(Some(default_response_hook));
-
-        make_sure_active(&mut client).await?;
+        let mut client = active_client(&data, &self.account, &self.password).await?;
 
-        let request = client.raw_client.get(url::MY_ACTIVITY).build()?;
+        let request = client.raw_client.get(url::MY_ACTIVITY.as_str()).build()?;
         let response = client.send(request).await?;
         let html = response.text().await?;
 
@@ -258,7 +330,7 @@ impl DoRequest for ScActivityRequest {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ScJoinRequest {
     pub account: String,
     pub password: String,
@@ -269,13 +341,9 @@ pub struct ScJoinRequest {
 #[async_trait::async_trait]
 impl DoRequest for ScJoinRequest {
     async fn process(self, mut data: SharedData) -> ResponseResult {
-        let session = data.session_store.query_or(&self.account, &self.password)?;
-        let mut client = UserClient::new(session, &data.client);
-        client.set_response_hook(Some(default_response_hook));
-
-        make_sure_active(&mut client).await?;
+        let mut client = active_client(&data, &self.account, &self.password).await?;
         if self.force {
-            let apply_url = format!("{}{}", url::APPLY_SUCCESS, self.activity_id);
+            let apply_url = format!("{}{}", *url::APPLY_SUCCESS, self.activity_id);
 
             let request = client.raw_client.get(apply_url).build()?;
             let response = client.send(request).await?;
@@ -286,7 +354,7 @@ impl DoRequest for ScJoinRequest {
 
             Ok(ResponsePayload::ScActivityJoin(result))
         } else {
-            let apply_url = format!("{}{}", url::APPLY_ACTIVITY, self.activity_id);
+            let apply_url = format!("{}{}", *url::APPLY_ACTIVITY, self.activity_id);
 
             let request = client.raw_client.post(apply_url).build()?;
             let response = client.send(request).await?;
@@ -298,7 +366,7 @@ impl DoRequest for ScJoinRequest {
             let message = ScJoinResult::from_html(&html_page)?;
             match message {
                 ScJoinResult::Ok => {
-                    let apply_url = format!("{}{}", url::APPLY_SUCCESS, self.activity_id);
+                    let apply_url = format!("{}{}", *url::APPLY_SUCCESS, self.activity_id);
 
                     let request = client.raw_client.get(apply_url).build()?;
                     client.send(request).await?;
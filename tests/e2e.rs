@@ -0,0 +1,275 @@
+//! End-to-end tests of the server link, with the agent talking to the mock server over TCP.
+//!
+//! The agent config is `tests/kite.toml`, with the database in a temporary directory and the
+//! fallback endpoint on a free port.
+
+#[macro_use]
+extern crate lazy_static;
+
+use std::net::{SocketAddr, TcpListener};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+use kite_agent::agent::codec::Codec;
+use kite_agent::agent::status::{self, ConnectionState};
+use kite_agent::agent::{self, worker, SharedData};
+use kite_agent::error::Result;
+use kite_agent::mock::{MockConnection, MockServer};
+use kite_agent::net::{self, SessionStorage};
+
+const SECRET: &str = "e2e-secret";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Test config and database, shared by tests since the database can't be opened twice in a process.
+struct TestEnv {
+    _dir: TempDir,
+    /// Fallback endpoint in the config, used by the worker test.
+    fallback: String,
+    storage: SessionStorage,
+}
+
+lazy_static! {
+    /// Points the agent to the test config, so touch it before anything reads `CONFIG`.
+    static ref ENV: TestEnv = {
+        let dir = tempfile::tempdir().expect("Fail to create temporary directory.");
+        let fallback = free_addr();
+
+        std::env::set_var("KITE_CONFIG", concat!(env!("CARGO_MANIFEST_DIR"), "/tests/kite.toml"));
+        std::env::set_var("KITE_AGENT__DB", dir.path().join("cache"));
+        std::env::set_var("KITE_SERVER__FALLBACK", format!("[{:?}]", fallback));
+        let storage = SessionStorage::new().expect("Fail to open session storage.");
+
+        TestEnv { _dir: dir, fallback, storage }
+    };
+}
+
+/// Local address not in use, by binding to port 0 and releasing it.
+fn free_addr() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Fail to find a free port.");
+
+    listener.local_addr().unwrap().to_string()
+}
+
+fn shared_data() -> SharedData {
+    let session_store = ENV.storage.clone();
+
+    SharedData {
+        node: "e2e".to_string(),
+        client: net::build_http_client().unwrap(),
+        session_store,
+        stream: None,
+        batch: None,
+        no_cache: false,
+    }
+}
+
+/// Connect to the address and serve on a new thread, until the connection is closed.
+fn spawn_agent(addr: SocketAddr) -> JoinHandle<Result<()>> {
+    let data = shared_data();
+
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let local = tokio::task::LocalSet::new();
+
+        local.block_on(&runtime, async move {
+            let connection = agent::connect(&addr.to_string(), "e2e").await?;
+            agent::serve(connection, data).await
+        })
+    })
+}
+
+async fn accept(server: &MockServer) -> Result<MockConnection> {
+    tokio::time::timeout(TIMEOUT, server.accept()).await?
+}
+
+async fn start() -> (MockConnection, JoinHandle<Result<()>>) {
+    let server = MockServer::bind("127.0.0.1:0", SECRET, Codec::Json)
+        .await
+        .unwrap();
+    let agent = spawn_agent(server.local_addr().unwrap());
+
+    (accept(&server).await.unwrap(), agent)
+}
+
+fn pong(s: &str) -> Value {
+    json!({ "Ok": { "Pong": s } })
+}
+
+#[tokio::test]
+async fn test_ping_and_agent_info() {
+    let (mut connection, _agent) = start().await;
+
+    assert_eq!(connection.hello.node, "e2e");
+    assert!(connection.hello.capabilities.iter().any(|kind| kind == "Ping"));
+    assert_eq!(connection.hello.codecs.first(), Some(&Codec::Json));
+
+    connection.request(1, json!({ "Ping": "hello" })).await.unwrap();
+    assert_eq!(connection.recv_response().await.unwrap(), (1, pong("hello")));
+
+    connection.request(2, json!({ "AgentInfo": null })).await.unwrap();
+    let (tag, payload) = connection.recv_response().await.unwrap();
+    assert_eq!(tag, 2);
+    let info = &payload["Ok"]["Credential"];
+    assert_eq!(info["name"], "e2e");
+    assert_eq!(info["systems"].as_array().unwrap().len(), 5);
+    // Probes are disabled in the test config.
+    assert_eq!(info["systems"][0]["reachable"], Value::Null);
+    assert!(info["in_flight"].as_i64().unwrap() >= 1);
+    assert!(info["workers"].is_array());
+    assert!(info["capabilities"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("AgentInfo")));
+}
+
+#[tokio::test]
+async fn test_concurrent_requests() {
+    let (connection, _agent) = start().await;
+    let mut client = connection.into_client();
+
+    let payloads = (0..32).map(|i| json!({ "Ping": i.to_string() })).collect();
+    let responses = client.call_all(payloads).await.unwrap();
+
+    for (i, response) in responses.iter().enumerate() {
+        assert_eq!(response, &pong(&i.to_string()));
+    }
+}
+
+#[tokio::test]
+async fn test_out_of_order_tags() {
+    let (mut connection, _agent) = start().await;
+
+    for tag in &[5, 3, 7] {
+        connection
+            .request(*tag, json!({ "Ping": tag.to_string() }))
+            .await
+            .unwrap();
+    }
+    // Neither cancelling an unknown tag nor heartbeat interrupts the link.
+    connection.cancel(99).await.unwrap();
+    connection.send(&json!("Heartbeat")).await.unwrap();
+
+    let mut tags = Vec::new();
+    for _ in 0..3 {
+        let (tag, payload) = connection.recv_response().await.unwrap();
+        assert_eq!(payload, pong(&tag.to_string()));
+        tags.push(tag);
+    }
+    tags.sort_unstable();
+    assert_eq!(tags, vec![3, 5, 7]);
+}
+
+#[tokio::test]
+async fn test_batch() {
+    let (mut connection, _agent) = start().await;
+    let batch = json!({ "Batch": [{ "Ping": "a" }, { "Ping": "b" }, { "Batch": [] }] });
+
+    connection.request(1, batch).await.unwrap();
+    let (_, payload) = connection.recv_response().await.unwrap();
+    let results = &payload["Ok"]["Batch"];
+
+    assert_eq!(results[0], pong("a"));
+    assert_eq!(results[1], pong("b"));
+    // Nested batch is rejected.
+    assert_eq!(results[2]["Err"]["code"], 56);
+}
+
+#[tokio::test]
+async fn test_job_submit_and_status() {
+    let (mut connection, _agent) = start().await;
+    let list = json!({ "ActivityList": { "count": 10, "index": 1, "category": 0 } });
+
+    // Jobs are not run without the job runner, so the job stays pending.
+    connection
+        .request(1, json!({ "Eventually": list }))
+        .await
+        .unwrap();
+    let (_, payload) = connection.recv_response().await.unwrap();
+    let id = payload["Ok"]["JobAccepted"].as_u64().unwrap();
+
+    connection.request(2, json!({ "JobStatus": id })).await.unwrap();
+    let (_, payload) = connection.recv_response().await.unwrap();
+    assert_eq!(payload["Ok"]["Job"]["Pending"]["attempts"], 0);
+
+    // Requests handled by agent itself are not deferrable.
+    connection
+        .request(3, json!({ "Eventually": { "Ping": "a" } }))
+        .await
+        .unwrap();
+    let (_, payload) = connection.recv_response().await.unwrap();
+    assert_eq!(payload["Err"]["code"], 56);
+}
+
+#[tokio::test]
+async fn test_malformed_frame() {
+    let (mut connection, agent) = start().await;
+
+    connection.send_raw(b"\xff\x00 not a frame").await.unwrap();
+    // Agent drops the connection.
+    let closed = tokio::time::timeout(TIMEOUT, async {
+        while let Ok(Some(_)) = connection.recv().await {}
+    })
+    .await;
+    assert!(closed.is_ok());
+    assert!(agent.join().unwrap().is_err());
+}
+
+#[tokio::test]
+async fn test_wrong_secret() {
+    let server = MockServer::bind("127.0.0.1:0", "wrong-secret", Codec::Json)
+        .await
+        .unwrap();
+    let agent = spawn_agent(server.local_addr().unwrap());
+
+    assert!(accept(&server).await.is_err());
+    assert!(agent.join().unwrap().is_err());
+}
+
+fn worker_status() -> Option<(ConnectionState, Option<String>)> {
+    status::snapshot()
+        .into_iter()
+        .find(|(id, _)| *id == 0)
+        .map(|(_, s)| (s.state, s.endpoint))
+}
+
+#[tokio::test]
+async fn test_worker_failover_and_reconnect() {
+    let data = shared_data();
+    let server = MockServer::bind(&ENV.fallback, SECRET, Codec::Json)
+        .await
+        .unwrap();
+    let (stop, stop_signal) = tokio::sync::watch::channel(());
+    let worker = std::thread::spawn(move || worker::run(0, data, stop_signal));
+
+    // The primary address is not available, so the worker connects to the fallback one.
+    let mut connection = accept(&server).await.unwrap();
+    connection.request(1, json!({ "Ping": "first" })).await.unwrap();
+    assert_eq!(connection.recv_response().await.unwrap(), (1, pong("first")));
+    assert_eq!(
+        worker_status(),
+        Some((ConnectionState::Connected, Some(ENV.fallback.clone())))
+    );
+
+    // Worker reconnects after the connection is lost.
+    drop(connection);
+    let mut connection = accept(&server).await.unwrap();
+    connection.request(2, json!({ "Ping": "second" })).await.unwrap();
+    assert_eq!(connection.recv_response().await.unwrap(), (2, pong("second")));
+
+    // Worker closes the connection and exits when stopped.
+    drop(stop);
+    let closed = tokio::time::timeout(TIMEOUT, async {
+        while let Ok(Some(_)) = connection.recv().await {}
+    })
+    .await;
+    assert!(closed.is_ok());
+    tokio::task::spawn_blocking(move || worker.join().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(worker_status(), None);
+}